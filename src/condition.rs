@@ -1,4 +1,9 @@
-#[derive(Debug)]
+use crate::instruction_set::normalize_name;
+
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
 pub enum Condition {
     Equal,
     NotEqual,
@@ -20,3 +25,22 @@ impl Condition {
         }
     }
 }
+
+impl FromStr for Condition {
+    type Err = String;
+
+    /// Parses a condition's name, so `LessEqual`, `less_equal` and
+    /// `less equal` are all the same
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let condition = match normalize_name(s).as_str() {
+            "equal" => Self::Equal,
+            "notequal" => Self::NotEqual,
+            "less" => Self::Less,
+            "lessequal" => Self::LessEqual,
+            "greater" => Self::Greater,
+            "greaterequal" => Self::GreaterEqual,
+            _ => return Err(format!("unknown condition `{}`", s)),
+        };
+        Ok(condition)
+    }
+}
@@ -0,0 +1,559 @@
+use crate::{
+    Direction, Halt, HaltReason, Instruction, Matrix, MatrixPoint, Pixel, StepOutcome, VMBuilder,
+    VM,
+};
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use termion::color;
+
+/// How many steps `back` and `rewind` can undo
+const HISTORY: usize = 100_000;
+
+/// How many pixels around the program counter are shown on every stop
+const RADIUS: usize = 3;
+
+const HELP: &str = "\
+step [n]             run n instructions (default 1)
+back [n]             undo n instructions (default 1)
+continue             run until a breakpoint, a watched cell changes or the program stops
+rewind <x>,<y>       undo instructions until the program counter was at x,y
+break <x>,<y>        stop before executing the pixel at x,y
+break <instruction>  stop before executing any instruction of this kind, e.g. `break pop until`
+watch <address>      stop when the tape cell at this address changes
+delete <n>           remove breakpoint n, leaving the others numbered as they were
+unwatch <address>    stop watching a tape cell
+print [stack|tape [address]|a|direction|calls]
+info                 list breakpoints and watched cells
+grid                 show the pixels around the program counter
+help                 show this message
+quit
+An empty line repeats the last command.";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Breakpoint {
+    Point(MatrixPoint),
+    Instruction(Instruction),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Item {
+    Stack,
+    Tape(Option<usize>),
+    RegisterA,
+    Direction,
+    Calls,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    Step(u64),
+    Back(u64),
+    Continue,
+    Rewind(MatrixPoint),
+    Break(Breakpoint),
+    Watch(usize),
+    Delete(usize),
+    Unwatch(usize),
+    Print(Option<Item>),
+    Info,
+    Grid,
+    Help,
+    Quit,
+}
+
+fn parse_point(s: &str) -> std::result::Result<MatrixPoint, String> {
+    let invalid = || format!("expected a position like `11,29`, got `{}`", s);
+    let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok(MatrixPoint(x, y))
+}
+
+fn parse_number<T: FromStr>(s: &str) -> std::result::Result<T, String> {
+    s.parse()
+        .map_err(|_| format!("expected a number, got `{}`", s))
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> std::result::Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let count = |rest: &str| match rest {
+            "" => Ok(1),
+            _ => parse_number(rest),
+        };
+
+        let command = match name {
+            "s" | "step" => Command::Step(count(rest)?),
+            "back" => Command::Back(count(rest)?),
+            "c" | "continue" => Command::Continue,
+            "rewind" => Command::Rewind(parse_point(rest)?),
+            "b" | "break" if rest.contains(',') => {
+                Command::Break(Breakpoint::Point(parse_point(rest)?))
+            }
+            "b" | "break" => Command::Break(Breakpoint::Instruction(rest.parse()?)),
+            "w" | "watch" => Command::Watch(parse_number(rest)?),
+            "d" | "delete" => Command::Delete(parse_number(rest)?),
+            "unwatch" => Command::Unwatch(parse_number(rest)?),
+            "p" | "print" => {
+                let (item, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+                let item = match item {
+                    "" => None,
+                    "stack" => Some(Item::Stack),
+                    "tape" if rest.is_empty() => Some(Item::Tape(None)),
+                    "tape" => Some(Item::Tape(Some(parse_number(rest.trim())?))),
+                    "a" => Some(Item::RegisterA),
+                    "direction" => Some(Item::Direction),
+                    "calls" => Some(Item::Calls),
+                    _ => return Err(format!("can't print `{}`", item)),
+                };
+                Command::Print(item)
+            }
+            "i" | "info" => Command::Info,
+            "g" | "grid" => Command::Grid,
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("unknown command `{}`, try `help`", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// Why running stopped, apart from running the requested number of steps
+enum Stop {
+    Breakpoint(usize),
+    Watch { address: usize, old: i64, new: i64 },
+    Halted(Halt),
+    Error,
+}
+
+struct Debugger<R: BufRead, W: Write> {
+    vm: VM<R, W>,
+    /// Breakpoints by number, which stays the same until it's deleted
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_breakpoint: usize,
+    watches: Vec<usize>,
+    /// Set once the program stops with a runtime error, until it's undone
+    failed: bool,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    fn new(mut vm: VM<R, W>, program: Matrix<Pixel>) -> Self {
+        vm.load(program);
+        Debugger {
+            vm,
+            breakpoints: BTreeMap::new(),
+            next_breakpoint: 0,
+            watches: vec![],
+            failed: false,
+        }
+    }
+
+    /// Runs a single command. Returns false once the user wants to quit.
+    fn execute<T: Write>(&mut self, command: Command, out: &mut T) -> io::Result<bool> {
+        match command {
+            Command::Step(n) => self.run(Some(n), out)?,
+            Command::Continue => self.run(None, out)?,
+            Command::Back(n) => {
+                let undone = (0..n).take_while(|_| self.vm.step_back()).count() as u64;
+                if undone < n {
+                    writeln!(out, "no more history")?;
+                }
+                self.failed &= undone == 0;
+                self.show(out)?;
+            }
+            Command::Rewind(point) => {
+                if !self.vm.run_back_to(point) {
+                    writeln!(out, "({}, {}) isn't in the history", point.0, point.1)?;
+                }
+                self.failed = false;
+                self.show(out)?;
+            }
+            Command::Break(breakpoint) => {
+                let n = self.next_breakpoint;
+                self.next_breakpoint += 1;
+                self.breakpoints.insert(n, breakpoint);
+                writeln!(out, "breakpoint {}", n)?;
+            }
+            Command::Watch(address) => match self.vm.tape().get(address) {
+                Ok(value) => {
+                    self.watches.push(address);
+                    writeln!(out, "watching tape[{}] = {}", address, value)?;
+                }
+                Err(e) => writeln!(out, "{}", e)?,
+            },
+            Command::Delete(n) => {
+                if self.breakpoints.remove(&n).is_none() {
+                    writeln!(out, "no breakpoint {}", n)?;
+                }
+            }
+            Command::Unwatch(address) => self.watches.retain(|&a| a != address),
+            Command::Print(item) => self.print(item, out)?,
+            Command::Info => self.info(out)?,
+            Command::Grid => self.grid(out)?,
+            Command::Help => writeln!(out, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Steps until something stops the program, or `limit` steps have run
+    fn run<T: Write>(&mut self, limit: Option<u64>, out: &mut T) -> io::Result<()> {
+        if self.failed {
+            writeln!(
+                out,
+                "the program stopped with an error, use `back` to undo it"
+            )?;
+            return Ok(());
+        }
+
+        let mut steps = 0;
+        let stop = loop {
+            if limit == Some(steps) {
+                break None;
+            }
+            // the first step ignores breakpoints, so continuing from one
+            // doesn't stop right away
+            if steps > 0 {
+                if let Some(n) = self.breakpoint() {
+                    break Some(Stop::Breakpoint(n));
+                }
+            }
+
+            let before = self.watched();
+            match self.vm.step() {
+                Ok(StepOutcome::Running) => {}
+                Ok(StepOutcome::Halted(halt)) => break Some(Stop::Halted(halt)),
+                Err(e) => {
+                    writeln!(out, "{}", e)?;
+                    self.failed = true;
+                    break Some(Stop::Error);
+                }
+            }
+            steps += 1;
+
+            let changed = self.watched().into_iter().zip(before).find(|(a, b)| a != b);
+            if let Some(((address, new), (_, old))) = changed {
+                break Some(Stop::Watch { address, old, new });
+            }
+        };
+
+        match stop {
+            Some(Stop::Breakpoint(n)) => writeln!(out, "breakpoint {}", n)?,
+            Some(Stop::Watch { address, old, new }) => {
+                writeln!(out, "tape[{}]: {} -> {}", address, old, new)?
+            }
+            Some(Stop::Halted(halt)) => match halt.reason {
+                HaltReason::EmptyStack => writeln!(out, "halted after {} steps", halt.steps)?,
+                HaltReason::Instruction(code) => {
+                    writeln!(out, "halted with code {} after {} steps", code, halt.steps)?
+                }
+            },
+            Some(Stop::Error) | None => {}
+        }
+        self.show(out)
+    }
+
+    /// The first breakpoint on the pixel about to be executed
+    fn breakpoint(&self) -> Option<usize> {
+        let next = self.vm.peek();
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| match breakpoint {
+                Breakpoint::Point(point) => *point == next.point,
+                Breakpoint::Instruction(instruction) => {
+                    *instruction == self.vm.instruction_set().instruction(next.value())
+                }
+            })
+            .map(|(&n, _)| n)
+    }
+
+    fn watched(&self) -> Vec<(usize, i64)> {
+        self.watches
+            .iter()
+            .map(|&address| (address, self.vm.tape().get(address).unwrap_or(0)))
+            .collect()
+    }
+
+    /// Where the program is, followed by the grid around it
+    fn show<T: Write>(&self, out: &mut T) -> io::Result<()> {
+        let pc = self.vm.pc();
+        write!(
+            out,
+            "step {} at ({}, {}) heading {:?}",
+            self.vm.steps(),
+            pc.0,
+            pc.1,
+            self.vm.direction()
+        )?;
+        if self.vm.thread_count() > 1 {
+            write!(out, " in thread {}", self.vm.thread())?;
+        }
+        if self.failed {
+            writeln!(out)?;
+        } else {
+            let next = self.vm.peek();
+            writeln!(
+                out,
+                ", next {:?} at ({}, {})",
+                self.vm.instruction_set().instruction(next.value()),
+                next.point.0,
+                next.point.1
+            )?;
+        }
+        self.grid(out)
+    }
+
+    fn grid<T: Write>(&self, out: &mut T) -> io::Result<()> {
+        let MatrixPoint(pc_x, pc_y) = self.vm.pc();
+        let instructions = self.vm.instructions();
+
+        for y in pc_y.saturating_sub(RADIUS)..=pc_y + RADIUS {
+            for x in pc_x.saturating_sub(RADIUS)..=pc_x + RADIUS {
+                let pixel = match instructions.get(MatrixPoint(x, y)) {
+                    Some(pixel) => pixel,
+                    None => {
+                        write!(out, "  ")?;
+                        continue;
+                    }
+                };
+
+                let [r, g, b] = pixel.rgb;
+                let cell = if (x, y) == (pc_x, pc_y) {
+                    match self.vm.direction() {
+                        Direction::North => "/\\",
+                        Direction::East => "->",
+                        Direction::South => "\\/",
+                        Direction::West => "<-",
+                    }
+                } else {
+                    "  "
+                };
+                // dark text on light pixels, so the program counter stays visible
+                let fg: &dyn color::Color = if pixel.hsl.l > 50 {
+                    &color::Black
+                } else {
+                    &color::White
+                };
+                write!(
+                    out,
+                    "{}{}{}",
+                    color::Bg(color::Rgb(r, g, b)),
+                    color::Fg(fg),
+                    cell
+                )?;
+                write!(
+                    out,
+                    "{}{}",
+                    color::Bg(color::Reset),
+                    color::Fg(color::Reset)
+                )?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    fn print<T: Write>(&self, item: Option<Item>, out: &mut T) -> io::Result<()> {
+        match item {
+            None => {
+                self.print(Some(Item::Stack), out)?;
+                self.print(Some(Item::RegisterA), out)?;
+                self.print(Some(Item::Direction), out)
+            }
+            Some(Item::Stack) => writeln!(out, "stack: {:?}", self.vm.stack()),
+            Some(Item::Tape(Some(address))) => match self.vm.tape().get(address) {
+                Ok(value) => writeln!(out, "tape[{}] = {}", address, value),
+                Err(e) => writeln!(out, "{}", e),
+            },
+            Some(Item::Tape(None)) => {
+                let cells = self.vm.tape().cells();
+                let mut written = cells.iter().enumerate().filter(|(_, &value)| value != 0);
+                match written.next() {
+                    None => writeln!(out, "every tape cell is 0"),
+                    Some(first) => {
+                        for (address, value) in std::iter::once(first).chain(written) {
+                            writeln!(out, "tape[{}] = {}", address, value)?;
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Some(Item::RegisterA) => writeln!(out, "A = {}", self.vm.register_a()),
+            Some(Item::Direction) => writeln!(out, "heading {:?}", self.vm.direction()),
+            Some(Item::Calls) => {
+                for (point, direction) in self.vm.calls().iter().rev() {
+                    writeln!(
+                        out,
+                        "called from ({}, {}) heading {:?}",
+                        point.0, point.1, direction
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn info<T: Write>(&self, out: &mut T) -> io::Result<()> {
+        for (n, breakpoint) in &self.breakpoints {
+            match breakpoint {
+                Breakpoint::Point(point) => writeln!(out, "{}: ({}, {})", n, point.0, point.1)?,
+                Breakpoint::Instruction(instruction) => writeln!(out, "{}: {:?}", n, instruction)?,
+            }
+        }
+        for (address, value) in self.watched() {
+            writeln!(out, "watching tape[{}] = {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Debugs `program` interactively, reading commands from stdin. The program
+/// reads its own input from `input` and writes its output to stdout.
+pub fn run<R: BufRead>(program: Matrix<Pixel>, builder: VMBuilder, input: R) -> Result<()> {
+    let vm = builder.history(HISTORY).build(input, io::stdout());
+    let mut debugger = Debugger::new(vm, program);
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut last = None;
+
+    debugger.show(&mut stdout)?;
+    loop {
+        write!(stdout, "(quilt) ")?;
+        stdout.flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        let command = match (line.trim(), last) {
+            ("", Some(last)) => Ok(last),
+            ("", None) => continue,
+            (line, _) => line.parse::<Command>(),
+        };
+        match command {
+            Ok(command) => {
+                last = Some(command);
+                if !debugger.execute(command, &mut stdout)? {
+                    return Ok(());
+                }
+            }
+            Err(e) => writeln!(stdout, "{}", e)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Breakpoint, Command, Debugger, Item};
+    use crate::pixel::START;
+    use crate::{Instruction, Matrix, MatrixPoint, VMBuilder};
+    use std::io::{self, BufReader, Empty};
+
+    fn init_debugger(pixels: Vec<Vec<u16>>) -> Debugger<BufReader<Empty>, Vec<u8>> {
+        let vm = VMBuilder::new()
+            .history(100)
+            .build(BufReader::new(io::empty()), vec![]);
+        Debugger::new(vm, Matrix::from_hues(pixels))
+    }
+
+    fn run(debugger: &mut Debugger<BufReader<Empty>, Vec<u8>>, command: &str) -> String {
+        let mut out = vec![];
+        debugger
+            .execute(command.parse().unwrap(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("step".parse(), Ok(Command::Step(1)));
+        assert_eq!("s 10".parse(), Ok(Command::Step(10)));
+        assert_eq!(
+            "break 11,29".parse(),
+            Ok(Command::Break(Breakpoint::Point(MatrixPoint(11, 29))))
+        );
+        assert_eq!(
+            "break pop until".parse(),
+            Ok(Command::Break(Breakpoint::Instruction(
+                Instruction::PopUntil
+            )))
+        );
+        assert_eq!(
+            "print tape 3".parse(),
+            Ok(Command::Print(Some(Item::Tape(Some(3)))))
+        );
+        assert!("break 11".parse::<Command>().is_err());
+        assert!("jump".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // PUSH 5, PUSH 7, ADD, POPA
+        let mut debugger = init_debugger(vec![vec![START, 36, 5, 36, 7, 108, 90]]);
+
+        run(&mut debugger, "break add");
+        let out = run(&mut debugger, "continue");
+        assert!(out.starts_with("breakpoint 0\nstep 2 at (4, 0)"));
+        assert_eq!(debugger.vm.stack(), &[5, 7]);
+
+        run(&mut debugger, "break 6,0");
+        assert!(run(&mut debugger, "continue").starts_with("breakpoint 1\n"));
+        assert_eq!(debugger.vm.stack(), &[12]);
+
+        run(&mut debugger, "watch 0");
+        let out = run(&mut debugger, "continue");
+        assert!(out.starts_with("tape[0]: 0 -> 12\n"));
+
+        run(&mut debugger, "delete 0");
+        assert!(run(&mut debugger, "continue").starts_with("halted after 5 steps\n"));
+    }
+
+    #[test]
+    fn test_delete() {
+        let mut debugger = init_debugger(vec![vec![START, 36, 5, 36, 7, 108, 90]]);
+        run(&mut debugger, "break add");
+        run(&mut debugger, "break 6,0");
+        run(&mut debugger, "break 3,0");
+
+        // deleting a breakpoint leaves the others' numbers alone
+        run(&mut debugger, "delete 0");
+        run(&mut debugger, "delete 1");
+        assert_eq!(run(&mut debugger, "info"), "2: (3, 0)\n");
+        assert_eq!(run(&mut debugger, "delete 1"), "no breakpoint 1\n");
+        assert!(run(&mut debugger, "break pop a").starts_with("breakpoint 3\n"));
+    }
+
+    #[test]
+    fn test_back() {
+        let mut debugger = init_debugger(vec![vec![START, 36, 5, 36, 7, 108, 90]]);
+        run(&mut debugger, "step 3");
+        assert_eq!(debugger.vm.stack(), &[12]);
+
+        run(&mut debugger, "back");
+        assert_eq!(debugger.vm.stack(), &[5, 7]);
+        run(&mut debugger, "rewind 2,0");
+        assert_eq!(debugger.vm.stack(), &[5]);
+        assert!(run(&mut debugger, "back 5").starts_with("no more history\n"));
+    }
+
+    #[test]
+    fn test_error() {
+        // PUSH 1, PUSH 0, DIV
+        let mut debugger = init_debugger(vec![vec![START, 36, 1, 36, 0, 162]]);
+        let out = run(&mut debugger, "continue");
+        assert!(out.starts_with("division by zero at (5, 0)"));
+        assert!(run(&mut debugger, "step").starts_with("the program stopped with an error"));
+
+        run(&mut debugger, "back");
+        assert_eq!(debugger.vm.stack(), &[1, 0]);
+        assert!(run(&mut debugger, "step").starts_with("division by zero"));
+    }
+}
@@ -1,7 +1,7 @@
 use super::pixel_widget::Pixel;
 use crate::parser::{parse, pixels};
 use crate::vm::Direction;
-use crate::{Matrix, MatrixPoint};
+use crate::{InstructionSet, Matrix, MatrixPoint};
 
 use anyhow::Result;
 use image::RgbaImage;
@@ -26,6 +26,8 @@ pub struct ImageEditor<'a> {
     last_hue: Option<u16>,
     /// Path to currently opened file
     path: String,
+    /// Which hue means which instruction
+    instruction_set: InstructionSet,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -36,7 +38,7 @@ pub enum State {
 }
 
 impl<'a> ImageEditor<'a> {
-    pub fn new(file: &str, pixel_size: u32) -> Self {
+    pub fn new(file: &str, pixel_size: u32, instruction_set: InstructionSet) -> Self {
         let pixels = parse(pixels(file, pixel_size).unwrap());
         Self {
             pixels,
@@ -47,6 +49,7 @@ impl<'a> ImageEditor<'a> {
             input: String::new(),
             last_hue: None,
             path: file.to_string(),
+            instruction_set,
         }
     }
 
@@ -68,12 +71,13 @@ impl<'a> ImageEditor<'a> {
         self.pixels
             .get(self.position)
             .map(|p| {
+                let instruction = self.instruction_set.instruction(p.value());
                 format!(
-                    "{}\n\ni: {:?}\nd: {}\nc: {:?}",
+                    "{}\n\ni: {}\nd: {}\nc: {:?}",
                     p.hsl,
-                    p.as_instruction(),
+                    self.instruction_set.mnemonic(instruction),
                     p.hsl.h,
-                    p.as_condition()
+                    self.instruction_set.condition(p.value())
                 )
             })
             .unwrap_or_else(|| "error".to_string())
@@ -107,7 +111,7 @@ impl<'a> ImageEditor<'a> {
     }
 
     pub fn replace_current(&mut self, hue: u16) {
-        self.pixels[self.position].hsl.h = hue.clamp(0, 359);
+        self.pixels[self.position].set_hue(hue.clamp(0, 359));
         self.last_hue = Some(hue);
     }
 
@@ -136,12 +140,12 @@ impl<'a> Widget for &ImageEditor<'a> {
         };
 
         for (y, row) in self.pixels.matrix.iter().enumerate() {
-            if y as usize >= area.height as usize {
+            if y >= area.height as usize {
                 return;
             }
             for (x, pixel) in row.iter().enumerate() {
                 // x takes up 2 cells
-                if x as usize * 2 + 1 >= area.width as usize {
+                if x * 2 + 1 >= area.width as usize {
                     break;
                 }
                 let pixel: Pixel = pixel.into();
@@ -3,6 +3,7 @@ mod pixel_widget;
 mod util;
 
 use crate::vm::Direction::{East, North, South, West};
+use crate::InstructionSet;
 use image_editor::{ImageEditor, State};
 use util::event::{Event, Events};
 
@@ -18,14 +19,14 @@ use tui::{
 
 use anyhow::anyhow;
 
-pub fn run(file: &str, pixel_size: u32) {
+pub fn run(file: &str, pixel_size: u32, instruction_set: InstructionSet) {
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode().unwrap();
     let stdout = MouseTerminal::from(stdout);
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend).unwrap();
-    let mut editor = ImageEditor::new(file, pixel_size);
+    let mut editor = ImageEditor::new(file, pixel_size, instruction_set);
 
     // Setup event handlers
     let mut events = Events::new();
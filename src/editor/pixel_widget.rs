@@ -35,6 +35,6 @@ impl Pixel {
 
 impl From<&crate::Pixel> for Pixel {
     fn from(p: &crate::Pixel) -> Self {
-        Self::new(p.point.0 as u16, p.point.1 as u16, p.hsl.into())
+        Self::new(p.point.0 as u16, p.point.1 as u16, p.rgba())
     }
 }
@@ -0,0 +1,175 @@
+use crate::vm::Direction;
+use crate::{Instruction, MatrixPoint};
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+/// A program that stopped without a runtime error
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Halt {
+    /// Number of instructions executed, including roads
+    pub steps: u64,
+    /// Where the program stopped
+    pub pc: MatrixPoint,
+    /// Why the program stopped
+    pub reason: HaltReason,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HaltReason {
+    /// An instruction popped from an empty stack (the graceful exit)
+    EmptyStack,
+    /// `HALT` stopped the program with this exit code
+    Instruction(i64),
+}
+
+impl Halt {
+    /// The status the program exits with: 0, unless `HALT` gave another
+    pub fn exit_code(&self) -> i64 {
+        match self.reason {
+            HaltReason::EmptyStack => 0,
+            HaltReason::Instruction(code) => code,
+        }
+    }
+
+    /// The exit code as a process exit status. Codes from 0 to 99 are kept
+    /// as they are, and any other code becomes 99, leaving the statuses above
+    /// it for errors and never exiting with 0 for a nonzero code.
+    pub fn status(&self) -> i32 {
+        match self.exit_code() {
+            code @ 0..=99 => code as i32,
+            _ => 99,
+        }
+    }
+}
+
+/// What went wrong during execution
+#[derive(Debug)]
+pub enum ErrorKind {
+    /// An instruction popped from an empty stack. `VM::execute` reports
+    /// this as [`HaltReason::EmptyStack`] rather than as an error.
+    StackUnderflow,
+    /// An instruction that takes an argument had no pixel to read it from
+    MissingArgument,
+    DivisionByZero,
+    /// An arithmetic result didn't fit in an `i64`, in the checked mode
+    Overflow,
+    /// The address register points outside of the tape
+    TapeOutOfRange {
+        address: usize,
+        size: usize,
+    },
+    /// The input could not be read as the requested type
+    InvalidInput(&'static str),
+    Io(io::Error),
+    /// The machine returned to a state it had already been in
+    InfiniteLoop,
+    /// `SPAWN` was executed without concurrency enabled
+    ThreadsDisabled,
+    /// `RETURN` was executed without a `CALL` to return to
+    ReturnStackUnderflow,
+    /// `CALL` was executed with `limit` calls already waiting to return
+    CallDepthExceeded {
+        limit: usize,
+    },
+    /// An instruction was given coordinates outside of the program
+    OutOfBounds {
+        x: i64,
+        y: i64,
+    },
+    /// `SYSCALL` asked for a function the host doesn't have
+    UnknownSyscall(i64),
+    /// A host function failed, for a reason of its own
+    Host(String),
+    /// A value written in the Unicode output mode isn't a Unicode scalar value
+    InvalidCodePoint(i64),
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackUnderflow => write!(f, "stack underflow"),
+            Self::MissingArgument => write!(f, "no arg supplied"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::Overflow => write!(f, "arithmetic overflow"),
+            Self::TapeOutOfRange { address, size } => {
+                write!(
+                    f,
+                    "tape address {} is out of range (size {})",
+                    address, size
+                )
+            }
+            Self::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::InfiniteLoop => write!(f, "program loops forever"),
+            Self::ThreadsDisabled => write!(f, "SPAWN needs concurrency to be enabled"),
+            Self::ReturnStackUnderflow => write!(f, "RETURN without a CALL to return to"),
+            Self::CallDepthExceeded { limit } => {
+                write!(f, "more than {} nested calls", limit)
+            }
+            Self::OutOfBounds { x, y } => write!(f, "({}, {}) is outside of the program", x, y),
+            Self::UnknownSyscall(number) => write!(f, "no host function {}", number),
+            Self::Host(reason) => write!(f, "host function failed: {}", reason),
+            Self::InvalidCodePoint(value) => write!(f, "{} is not a Unicode code point", value),
+        }
+    }
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// A runtime error, along with where in the program it happened
+#[derive(Debug)]
+pub struct QuiltError {
+    pub kind: ErrorKind,
+    pub pc: MatrixPoint,
+    pub direction: Direction,
+    pub instruction: Instruction,
+}
+
+impl Display for QuiltError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at ({}, {}) heading {:?} while executing {:?}",
+            self.kind, self.pc.0, self.pc.1, self.direction, self.instruction
+        )
+    }
+}
+
+impl Error for QuiltError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Halt, HaltReason};
+    use crate::MatrixPoint;
+
+    #[test]
+    fn test_status() {
+        let status = |reason| {
+            Halt {
+                steps: 0,
+                pc: MatrixPoint(0, 0),
+                reason,
+            }
+            .status()
+        };
+        assert_eq!(status(HaltReason::EmptyStack), 0);
+        assert_eq!(status(HaltReason::Instruction(7)), 7);
+        assert_eq!(status(HaltReason::Instruction(99)), 99);
+        for code in [100, 255, 256, -1, -256, i64::MAX, i64::MIN] {
+            assert_eq!(status(HaltReason::Instruction(code)), 99, "{}", code);
+        }
+    }
+}
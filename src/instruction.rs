@@ -1,36 +1,129 @@
-#[derive(Debug, PartialEq)]
+use crate::instruction_set::normalize_name;
+
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
 pub enum Instruction {
-    PushA,       // push tape[registerA] to the stack
-    PopUntil,    // pops until hitting 0
-    Push,        // pushes a u16 onto the stack
-    Save,        // saves u16 into tape[registerA]
-    MovA,        // moves an address into the registerA
-    PopA,        // pops the stack and saves into tape[registerA]
-    Add,         // pops the stack twice, adds the numbers & pushes the result
-    Sub,         // pops the stack twice, subs the numbers & pushes the result
-    Mult,        // pops the stack twice, multiplies the numbers & pushes the result
-    Div,         // pops the stack twice, divides the numbers & pushes the result
-    Road,        // where the program goes
-    LeftShift,   // pops the stack once, shifts the number left one & pushes the result
-    RightShift,  // pops the stack once, shifts the number right one & pushes the result
-    And,         // pops the stack twice, bitwise ands the numbers & pushes the result
-    Or,          // pops the stack twice, bitwise ors the numbers & pushes the result
-    Not,         // pops the stack once, bitwise negates the number & pushes the result
-    Xor,         // pops the stack twice, bitwise xors the numbers & pushes the result
-    Output,      // outputs & pops the top of the stack to stdout
-    OutputUntil, // outputs & pops the top of the stack to stdout until a 0 is reached
-    Modulo,      // pops the stack twice, divides the numbers & pushes the remainder
-    Start,       // where the program starts
+    PushA,        // push tape[registerA] to the stack
+    PopUntil,     // pops until hitting 0
+    Push,         // pushes a u16 onto the stack
+    Save,         // saves u16 into tape[registerA]
+    MovA,         // moves an address into the registerA
+    PopA,         // pops the stack and saves into tape[registerA]
+    Add,          // pops the stack twice, adds the numbers & pushes the result
+    Sub,          // pops the stack twice, subs the numbers & pushes the result
+    Mult,         // pops the stack twice, multiplies the numbers & pushes the result
+    Div,          // pops the stack twice, divides the numbers & pushes the result
+    Road,         // where the program goes
+    LeftShift,    // pops the stack once, shifts the number left one & pushes the result
+    RightShift,   // pops the stack once, shifts the number right one & pushes the result
+    And,          // pops the stack twice, bitwise ands the numbers & pushes the result
+    Or,           // pops the stack twice, bitwise ors the numbers & pushes the result
+    Not,          // pops the stack once, bitwise negates the number & pushes the result
+    Xor,          // pops the stack twice, bitwise xors the numbers & pushes the result
+    Output,       // outputs & pops the top of the stack to stdout
+    OutputUntil,  // outputs & pops the top of the stack to stdout until a 0 is reached
+    Modulo,       // pops the stack twice, divides the numbers & pushes the remainder
+    Start,        // where the program starts
+    Read,         // reads a byte from the input & pushes it (-1 on EOF)
+    ReadInt,      // reads a decimal integer from the input & pushes it (-1 on EOF)
+    OutputInt,    // pops the top of the stack & outputs it as a decimal integer
+    OutputIntSep, // like OutputInt, followed by the character given by its argument
+    Spawn,        // starts a new thread here, heading the opposite way with a copy of the stack
+    Join,         // waits here until every other thread has finished
+    Call,         // pops y then x & continues from (x, y), remembering where to return to
+    Return,       // continues from the last CALL, heading the same way as when it was called
+    Branch,       // pops & turns clockwise if the condition holds, otherwise counter-clockwise
+    Dup,          // pushes a copy of the top of the stack
+    Swap,         // swaps the top two values of the stack
+    Over,         // pushes a copy of the value below the top of the stack
+    Rot,          // moves the third value from the top of the stack to the top
+    Drop,         // pops the stack & discards the value
+    Depth,        // pushes the number of values on the stack
+    WidePush,     // pushes the argument's whole color as a signed 24-bit number
+    VarPush,      // pushes the base-360 number in the pixels up to the next gray one
+    Syscall,      // pops a function number & calls that function of the host
+    Halt,         // pops an exit code (0 if the stack is empty) & stops the program
+    Random,       // pops high then low & pushes a random number from low to high
+    Get,          // pops y then x & pushes the hue of the pixel at (x, y)
+    Put,          // pops y, x then a hue & changes the pixel at (x, y) to that hue
+    Jump,         // pops a direction, y then x & continues from (x, y) heading that way
 
     None, // just data
 }
 
 impl Instruction {
     pub fn takes_arg(&self) -> bool {
-        matches!(self, Self::Push | Self::MovA | Self::Save)
+        matches!(
+            self,
+            Self::Push
+                | Self::MovA
+                | Self::Save
+                | Self::OutputIntSep
+                | Self::WidePush
+                | Self::VarPush
+        )
     }
 
     pub fn is_conditional(&self) -> bool {
-        matches!(self, Self::PopUntil | Self::OutputUntil)
+        matches!(self, Self::PopUntil | Self::OutputUntil | Self::Branch)
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = String;
+
+    /// Parses an instruction's name, so `OUTPUT UNTIL`, `output_until` and
+    /// `OutputUntil` are all the same
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let instruction = match normalize_name(s).as_str() {
+            "pusha" => Self::PushA,
+            "popuntil" => Self::PopUntil,
+            "push" => Self::Push,
+            "save" => Self::Save,
+            "mova" => Self::MovA,
+            "popa" => Self::PopA,
+            "add" => Self::Add,
+            "sub" => Self::Sub,
+            "mult" => Self::Mult,
+            "div" => Self::Div,
+            "road" => Self::Road,
+            "leftshift" => Self::LeftShift,
+            "rightshift" => Self::RightShift,
+            "and" => Self::And,
+            "or" => Self::Or,
+            "not" => Self::Not,
+            "xor" => Self::Xor,
+            "output" => Self::Output,
+            "outputuntil" => Self::OutputUntil,
+            "modulo" => Self::Modulo,
+            "start" => Self::Start,
+            "read" => Self::Read,
+            "readint" => Self::ReadInt,
+            "outputint" => Self::OutputInt,
+            "outputintsep" => Self::OutputIntSep,
+            "spawn" => Self::Spawn,
+            "join" => Self::Join,
+            "call" => Self::Call,
+            "return" => Self::Return,
+            "branch" => Self::Branch,
+            "dup" => Self::Dup,
+            "swap" => Self::Swap,
+            "over" => Self::Over,
+            "rot" => Self::Rot,
+            "drop" => Self::Drop,
+            "depth" => Self::Depth,
+            "widepush" => Self::WidePush,
+            "varpush" => Self::VarPush,
+            "syscall" => Self::Syscall,
+            "halt" => Self::Halt,
+            "random" => Self::Random,
+            "get" => Self::Get,
+            "put" => Self::Put,
+            "jump" => Self::Jump,
+            _ => return Err(format!("unknown instruction `{}`", s)),
+        };
+        Ok(instruction)
     }
 }
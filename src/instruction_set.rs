@@ -0,0 +1,721 @@
+use crate::pixel::START;
+use crate::{Condition, Instruction};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Hues go from 0 to 360, both included
+const HUES: usize = 361;
+
+/// Where an instruction sits on the colour wheel, and how it's documented
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstructionSpec {
+    pub instruction: Instruction,
+    /// How the instruction is written in the docs, e.g. `PUSH <number>`
+    pub mnemonic: String,
+    pub hues: RangeInclusive<u16>,
+    /// Number of argument pixels that follow the instruction, which has to
+    /// match what the VM reads for it
+    pub arity: u8,
+    pub description: String,
+}
+
+impl InstructionSpec {
+    pub fn new(
+        instruction: Instruction,
+        mnemonic: &str,
+        hues: RangeInclusive<u16>,
+        description: &str,
+    ) -> Self {
+        InstructionSpec {
+            instruction,
+            mnemonic: mnemonic.to_string(),
+            hues,
+            arity: instruction.takes_arg() as u8,
+            description: description.to_string(),
+        }
+    }
+}
+
+/// Where a condition sits on the colour wheel. Corners whose hue isn't in
+/// any condition's range compare with [`Condition::Equal`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionSpec {
+    pub condition: Condition,
+    pub hues: RangeInclusive<u16>,
+}
+
+/// Which hue means which instruction and which condition. The standard set is
+/// the one in `commands.md`; others can be loaded from TOML files like this:
+///
+/// ```toml
+/// [[instruction]]
+/// name = "push"
+/// hues = [30, 50]
+/// arity = 1
+///
+/// [[instruction]]
+/// name = "output until"
+/// hues = [300, 330]
+/// description = "prints the stack"
+///
+/// [[condition]]
+/// name = "not equal"
+/// hues = [0, 20]
+/// ```
+///
+/// An instruction's mnemonic and description default to the standard set's,
+/// and a file without any conditions uses the standard ones. An arity can be
+/// given to check it against the instruction, but can't change it.
+#[derive(Clone, Debug)]
+pub struct InstructionSet {
+    instructions: Vec<InstructionSpec>,
+    conditions: Vec<ConditionSpec>,
+    /// The instruction for every hue, so decoding a pixel is a lookup
+    by_hue: Vec<Instruction>,
+    condition_by_hue: Vec<Condition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SetFile {
+    #[serde(default, rename = "instruction")]
+    instructions: Vec<InstructionEntry>,
+    #[serde(default, rename = "condition")]
+    conditions: Vec<ConditionEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstructionEntry {
+    name: String,
+    hues: [u16; 2],
+    mnemonic: Option<String>,
+    description: Option<String>,
+    arity: Option<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionEntry {
+    name: String,
+    hues: [u16; 2],
+}
+
+impl InstructionSet {
+    /// Fails if a range is backwards or goes past 360, if an instruction is
+    /// listed twice or with the wrong arity, or if two instructions (or two
+    /// conditions) share a hue
+    pub fn new(instructions: Vec<InstructionSpec>, conditions: Vec<ConditionSpec>) -> Result<Self> {
+        let mut by_hue = vec![Instruction::None; HUES];
+        let mut owners: Vec<Option<&str>> = vec![None; HUES];
+        for (i, spec) in instructions.iter().enumerate() {
+            check_range(&spec.hues, &spec.mnemonic)?;
+            let arity = spec.instruction.takes_arg() as u8;
+            if spec.arity != arity {
+                bail!(
+                    "{} takes {} argument pixels, not {}",
+                    spec.mnemonic,
+                    arity,
+                    spec.arity
+                );
+            }
+            if instructions[..i]
+                .iter()
+                .any(|other| other.instruction == spec.instruction)
+            {
+                bail!("{} is in the instruction set twice", spec.mnemonic);
+            }
+            for hue in spec.hues.clone() {
+                if let Some(owner) = owners[hue as usize] {
+                    bail!("{} and {} both use hue {}", owner, spec.mnemonic, hue);
+                }
+                owners[hue as usize] = Some(&spec.mnemonic);
+                by_hue[hue as usize] = spec.instruction;
+            }
+        }
+
+        let mut condition_by_hue = vec![Condition::Equal; HUES];
+        let mut taken = vec![false; HUES];
+        for spec in &conditions {
+            let name = format!("{:?}", spec.condition);
+            check_range(&spec.hues, &name)?;
+            for hue in spec.hues.clone() {
+                if taken[hue as usize] {
+                    bail!("more than one condition uses hue {}", hue);
+                }
+                taken[hue as usize] = true;
+                condition_by_hue[hue as usize] = spec.condition;
+            }
+        }
+
+        Ok(InstructionSet {
+            instructions,
+            conditions,
+            by_hue,
+            condition_by_hue,
+        })
+    }
+
+    /// The instruction set described in `commands.md`
+    pub fn standard() -> &'static InstructionSet {
+        static STANDARD: OnceLock<InstructionSet> = OnceLock::new();
+        STANDARD.get_or_init(|| {
+            InstructionSet::new(standard_instructions(), standard_conditions())
+                .expect("the standard instruction set is valid")
+        })
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self> {
+        let file: SetFile = toml::from_str(source)?;
+        let instructions = file
+            .instructions
+            .into_iter()
+            .map(|entry| {
+                let instruction: Instruction = entry.name.parse().map_err(|e| anyhow!("{}", e))?;
+                let standard = Self::standard().spec(instruction);
+                let mnemonic = entry
+                    .mnemonic
+                    .or_else(|| standard.map(|spec| spec.mnemonic.clone()))
+                    .unwrap_or(entry.name);
+                let description = entry
+                    .description
+                    .or_else(|| standard.map(|spec| spec.description.clone()))
+                    .unwrap_or_default();
+                let [start, end] = entry.hues;
+                let mut spec =
+                    InstructionSpec::new(instruction, &mnemonic, start..=end, &description);
+                if let Some(arity) = entry.arity {
+                    spec.arity = arity;
+                }
+                Ok(spec)
+            })
+            .collect::<Result<_>>()?;
+
+        let conditions = if file.conditions.is_empty() {
+            standard_conditions()
+        } else {
+            file.conditions
+                .into_iter()
+                .map(|entry| {
+                    let condition = entry.name.parse().map_err(|e| anyhow!("{}", e))?;
+                    let [start, end] = entry.hues;
+                    Ok(ConditionSpec {
+                        condition,
+                        hues: start..=end,
+                    })
+                })
+                .collect::<Result<_>>()?
+        };
+
+        Self::new(instructions, conditions)
+    }
+
+    /// Reads an instruction set from a TOML file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        Self::from_toml(&source).map_err(|e| anyhow!("{}: {}", path.display(), e))
+    }
+
+    /// The instruction a pixel of this hue stands for
+    pub fn instruction(&self, hue: u16) -> Instruction {
+        self.by_hue
+            .get(hue as usize)
+            .copied()
+            .unwrap_or(Instruction::None)
+    }
+
+    /// The condition a corner pixel of this hue stands for
+    pub fn condition(&self, hue: u16) -> Condition {
+        self.condition_by_hue
+            .get(hue as usize)
+            .copied()
+            .unwrap_or(Condition::Equal)
+    }
+
+    pub fn spec(&self, instruction: Instruction) -> Option<&InstructionSpec> {
+        self.instructions
+            .iter()
+            .find(|spec| spec.instruction == instruction)
+    }
+
+    pub fn instructions(&self) -> &[InstructionSpec] {
+        &self.instructions
+    }
+
+    pub fn conditions(&self) -> &[ConditionSpec] {
+        &self.conditions
+    }
+
+    /// How an instruction is written in the docs, falling back to its name
+    pub fn mnemonic(&self, instruction: Instruction) -> String {
+        self.spec(instruction)
+            .map(|spec| spec.mnemonic.clone())
+            .unwrap_or_else(|| format!("{:?}", instruction).to_uppercase())
+    }
+
+    /// A markdown table of the instructions, as found in `commands.md`
+    pub fn instructions_table(&self) -> String {
+        let rows = self
+            .instructions
+            .iter()
+            .map(|spec| {
+                vec![
+                    format!("`{}`", spec.mnemonic),
+                    hue_range(&spec.hues),
+                    spec.description.clone(),
+                ]
+            })
+            .collect();
+        table(["command name", "hue range", "description"], rows)
+    }
+
+    /// A markdown table of the conditions, as found in the README
+    pub fn conditions_table(&self) -> String {
+        let default = vec!["default".to_string(), format!("{:?}", Condition::Equal)];
+        let rows = std::iter::once(default)
+            .chain(
+                self.conditions
+                    .iter()
+                    .map(|spec| vec![hue_range(&spec.hues), format!("{:?}", spec.condition)]),
+            )
+            .collect();
+        table(["hue range", "comparison"], rows)
+    }
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        Self::standard().clone()
+    }
+}
+
+/// Lowercases an instruction or condition name and drops its spaces and
+/// underscores, so names can be written however reads best
+pub(crate) fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_'))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn check_range(hues: &RangeInclusive<u16>, name: &str) -> Result<()> {
+    if hues.start() > hues.end() || *hues.end() as usize >= HUES {
+        bail!(
+            "{} has hues {}, which isn't a range within 0-360",
+            name,
+            hue_range(hues)
+        );
+    }
+    Ok(())
+}
+
+fn hue_range(hues: &RangeInclusive<u16>) -> String {
+    if hues.start() == hues.end() {
+        hues.start().to_string()
+    } else {
+        format!("{}-{}", hues.start(), hues.end())
+    }
+}
+
+/// Lays out a markdown table with its columns lined up
+fn table<const N: usize>(headers: [&str; N], rows: Vec<Vec<String>>) -> String {
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    let mut line = |cells: Vec<String>| {
+        for (cell, width) in cells.iter().zip(widths) {
+            write!(out, "| {:width$} ", cell, width = width).unwrap();
+        }
+        out.push_str("|\n");
+    };
+    line(headers.iter().map(|header| header.to_string()).collect());
+    for row in rows {
+        line(row);
+    }
+
+    let rule: String = widths
+        .iter()
+        .map(|width| format!("|{}", "-".repeat(width + 2)))
+        .collect();
+    let header_end = out.find('\n').unwrap() + 1;
+    out.insert_str(header_end, &format!("{}|\n", rule));
+    out
+}
+
+fn standard_instructions() -> Vec<InstructionSpec> {
+    use Instruction::*;
+    let spec = InstructionSpec::new;
+    vec![
+        spec(PushA, "PUSHA", 0..=8, "pushes `tape[A]`"),
+        spec(
+            PopUntil,
+            "POP UNTIL",
+            18..=26,
+            "pops until a value meets the condition",
+        ),
+        spec(Push, "PUSH <number>", 36..=44, "pushes the argument's hue"),
+        spec(
+            Save,
+            "SAVE <number>",
+            54..=62,
+            "writes the argument's hue to `tape[A]`",
+        ),
+        spec(
+            MovA,
+            "MOVA <address>",
+            72..=80,
+            "sets A to the argument's hue",
+        ),
+        spec(PopA, "POPA", 90..=98, "pops into `tape[A]`"),
+        spec(Add, "ADD", 108..=116, "pops twice & pushes the sum"),
+        spec(Sub, "SUB", 126..=134, "pops twice & pushes the difference"),
+        spec(Mult, "MULT", 144..=152, "pops twice & pushes the product"),
+        spec(Div, "DIV", 162..=170, "pops twice & pushes the quotient"),
+        spec(Road, "ROAD", 180..=188, "where the program goes"),
+        spec(
+            LeftShift,
+            "LEFTSHIFT",
+            198..=206,
+            "pops & pushes it shifted left by one",
+        ),
+        spec(
+            RightShift,
+            "RIGHTSHIFT",
+            216..=224,
+            "pops & pushes it shifted right by one",
+        ),
+        spec(And, "AND", 234..=242, "pops twice & pushes the bitwise and"),
+        spec(Or, "OR", 252..=260, "pops twice & pushes the bitwise or"),
+        spec(Not, "NOT", 270..=278, "pops & pushes its bitwise negation"),
+        spec(Xor, "XOR", 288..=296, "pops twice & pushes the bitwise xor"),
+        spec(Output, "OUTPUT", 306..=314, "pops & outputs it"),
+        spec(
+            OutputUntil,
+            "OUTPUT UNTIL",
+            324..=332,
+            "pops & outputs until a value meets the condition",
+        ),
+        spec(
+            Modulo,
+            "MODULO",
+            342..=350,
+            "pops twice & pushes the remainder",
+        ),
+        spec(Start, "START", START..=START, "where the program starts"),
+        spec(Read, "READ", 9..=12, "reads a byte & pushes it (-1 on EOF)"),
+        spec(
+            ReadInt,
+            "READ INT",
+            14..=17,
+            "reads a decimal integer & pushes it (-1 on EOF)",
+        ),
+        spec(
+            OutputInt,
+            "OUTPUT INT",
+            27..=30,
+            "pops & outputs it as a decimal integer",
+        ),
+        spec(
+            OutputIntSep,
+            "OUTPUT INT <separator>",
+            32..=35,
+            "like `OUTPUT INT`, followed by the argument's character",
+        ),
+        spec(
+            Spawn,
+            "SPAWN",
+            45..=48,
+            "starts a thread heading the opposite way with a copy of the stack",
+        ),
+        spec(
+            Join,
+            "JOIN",
+            50..=53,
+            "waits until every other thread has finished",
+        ),
+        spec(
+            Call,
+            "CALL",
+            63..=66,
+            "pops y then x & continues from (x, y)",
+        ),
+        spec(Return, "RETURN", 68..=71, "continues from the last `CALL`"),
+        spec(
+            Branch,
+            "BRANCH",
+            81..=89,
+            "pops & turns clockwise if the condition holds, otherwise counter-clockwise",
+        ),
+        spec(
+            Dup,
+            "DUP",
+            99..=101,
+            "pushes a copy of the top of the stack",
+        ),
+        spec(
+            Swap,
+            "SWAP",
+            102..=104,
+            "swaps the top two values of the stack",
+        ),
+        spec(
+            Over,
+            "OVER",
+            105..=107,
+            "pushes a copy of the value below the top of the stack",
+        ),
+        spec(
+            Rot,
+            "ROT",
+            117..=119,
+            "moves the third value from the top of the stack to the top",
+        ),
+        spec(Drop, "DROP", 120..=122, "pops & discards the value"),
+        spec(
+            Depth,
+            "DEPTH",
+            123..=125,
+            "pushes the number of values on the stack",
+        ),
+        spec(
+            WidePush,
+            "WIDE PUSH <color>",
+            135..=138,
+            "pushes the argument's color as a signed 24-bit number",
+        ),
+        spec(
+            VarPush,
+            "VAR PUSH <digits>",
+            140..=143,
+            "pushes the base-360 number in the pixels up to the next gray one",
+        ),
+        spec(
+            Syscall,
+            "SYSCALL",
+            153..=161,
+            "pops a function number & calls that function of the host",
+        ),
+        spec(
+            Halt,
+            "HALT",
+            171..=179,
+            "pops an exit code (0 if the stack is empty) & stops the program",
+        ),
+        spec(
+            Random,
+            "RANDOM",
+            189..=197,
+            "pops high then low & pushes a random number from low to high",
+        ),
+        spec(
+            Get,
+            "GET",
+            207..=210,
+            "pops y then x & pushes the hue of the pixel at (x, y)",
+        ),
+        spec(
+            Put,
+            "PUT",
+            212..=215,
+            "pops y, x then a hue & changes the pixel at (x, y) to that hue",
+        ),
+        spec(
+            Jump,
+            "JUMP",
+            225..=233,
+            "pops a direction, y then x & continues from (x, y) heading that way",
+        ),
+    ]
+}
+
+fn standard_conditions() -> Vec<ConditionSpec> {
+    use Condition::*;
+    [
+        (NotEqual, 0..=8),
+        (Less, 72..=80),
+        (LessEqual, 144..=152),
+        (Greater, 216..=224),
+        (GreaterEqual, 288..=296),
+    ]
+    .into_iter()
+    .map(|(condition, hues)| ConditionSpec { condition, hues })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_standard_set() {
+        let set = InstructionSet::standard();
+        assert_eq!(set.instruction(0), Instruction::PushA);
+        assert_eq!(set.instruction(13), Instruction::None);
+        assert_eq!(set.instruction(36), Instruction::Push);
+        assert_eq!(set.instruction(300), Instruction::Start);
+        assert_eq!(set.instruction(360), Instruction::None);
+        assert_eq!(set.instruction(1000), Instruction::None);
+        assert_eq!(set.condition(75), Condition::Less);
+        assert_eq!(set.condition(36), Condition::Equal);
+        assert_eq!(set.spec(Instruction::Push).unwrap().arity, 1);
+        assert_eq!(set.spec(Instruction::Add).unwrap().arity, 0);
+    }
+
+    #[test]
+    fn test_standard_set_covers_every_hue() {
+        use Instruction::*;
+        let set = InstructionSet::standard();
+        for hue in 0..HUES as u16 {
+            let instruction = match hue {
+                0..=8 => PushA,
+                9..=12 => Read,
+                14..=17 => ReadInt,
+                18..=26 => PopUntil,
+                27..=30 => OutputInt,
+                32..=35 => OutputIntSep,
+                36..=44 => Push,
+                45..=48 => Spawn,
+                50..=53 => Join,
+                54..=62 => Save,
+                63..=66 => Call,
+                68..=71 => Return,
+                72..=80 => MovA,
+                81..=89 => Branch,
+                90..=98 => PopA,
+                99..=101 => Dup,
+                102..=104 => Swap,
+                105..=107 => Over,
+                108..=116 => Add,
+                117..=119 => Rot,
+                120..=122 => Drop,
+                123..=125 => Depth,
+                126..=134 => Sub,
+                135..=138 => WidePush,
+                140..=143 => VarPush,
+                144..=152 => Mult,
+                153..=161 => Syscall,
+                162..=170 => Div,
+                171..=179 => Halt,
+                180..=188 => Road,
+                189..=197 => Random,
+                198..=206 => LeftShift,
+                207..=210 => Get,
+                212..=215 => Put,
+                216..=224 => RightShift,
+                225..=233 => Jump,
+                234..=242 => And,
+                252..=260 => Or,
+                270..=278 => Not,
+                288..=296 => Xor,
+                300 => Start,
+                306..=314 => Output,
+                324..=332 => OutputUntil,
+                342..=350 => Modulo,
+                _ => None,
+            };
+            assert_eq!(set.instruction(hue), instruction, "hue {}", hue);
+        }
+    }
+
+    #[test]
+    fn test_docs_match_standard_set() {
+        let set = InstructionSet::standard();
+        let commands = include_str!("../commands.md");
+        let readme = include_str!("../README.md");
+        assert_eq!(commands, set.instructions_table());
+        assert!(readme.contains(&set.instructions_table()));
+        assert!(readme.contains(&set.conditions_table()));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let set = InstructionSet::from_toml(
+            r#"
+            [[instruction]]
+            name = "push"
+            hues = [30, 50]
+            arity = 1
+
+            [[instruction]]
+            name = "OUTPUT UNTIL"
+            hues = [300, 330]
+            description = "prints the stack"
+
+            [[condition]]
+            name = "less"
+            hues = [0, 20]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(set.instruction(30), Instruction::Push);
+        assert_eq!(set.instruction(50), Instruction::Push);
+        assert_eq!(set.instruction(310), Instruction::OutputUntil);
+        assert_eq!(set.instruction(0), Instruction::None);
+        assert_eq!(set.instruction(180), Instruction::None);
+        assert_eq!(set.condition(10), Condition::Less);
+        assert_eq!(set.condition(75), Condition::Equal);
+        assert_eq!(set.mnemonic(Instruction::Push), "PUSH <number>");
+        assert_eq!(
+            set.spec(Instruction::OutputUntil).unwrap().description,
+            "prints the stack"
+        );
+    }
+
+    #[test]
+    fn test_from_toml_errors() {
+        let overlap = r#"
+            [[instruction]]
+            name = "push"
+            hues = [30, 50]
+
+            [[instruction]]
+            name = "add"
+            hues = [50, 60]
+            "#;
+        let backwards = r#"
+            [[instruction]]
+            name = "push"
+            hues = [50, 30]
+            "#;
+        let too_high = r#"
+            [[instruction]]
+            name = "push"
+            hues = [350, 361]
+            "#;
+        let twice = r#"
+            [[instruction]]
+            name = "push"
+            hues = [0, 1]
+
+            [[instruction]]
+            name = "push"
+            hues = [2, 3]
+            "#;
+        let unknown = r#"
+            [[instruction]]
+            name = "teleport"
+            hues = [0, 1]
+            "#;
+        let arity = r#"
+            [[instruction]]
+            name = "add"
+            hues = [0, 1]
+            arity = 1
+            "#;
+        for source in [overlap, backwards, too_high, twice, unknown, arity] {
+            assert!(InstructionSet::from_toml(source).is_err(), "{}", source);
+        }
+    }
+}
@@ -1,25 +1,39 @@
 mod condition;
+pub mod debugger;
 pub mod editor;
+mod error;
 mod hsl;
 mod instruction;
+mod instruction_set;
 mod matrix;
 mod parser;
 mod pixel;
 mod vm;
 
 pub use condition::Condition;
+pub use error::{ErrorKind, Halt, HaltReason, QuiltError};
 pub use hsl::Hsl;
 pub use instruction::Instruction;
+pub use instruction_set::{ConditionSpec, InstructionSet, InstructionSpec};
 pub use matrix::{Matrix, MatrixPoint};
 pub use pixel::Pixel;
-pub use vm::VM;
+pub use vm::{
+    ArithmeticMode, Direction, Host, Machine, NoHost, OutputMode, StepOutcome, Syscalls, Tape,
+    TapeSize, VMBuilder, DEFAULT_CALL_DEPTH, DEFAULT_SEED, VM,
+};
 
 use parser::{parse, pixels};
 
-use std::io::Write;
+use anyhow::Result;
+use std::io::{BufRead, Write};
 
-pub fn run<T: Write>(file: &str, pixel_size: u32, out: T) {
-    let program = parse(pixels(file, pixel_size).unwrap());
-    let mut vm = VM::new(out);
-    vm.execute(program);
+/// Reads a program from an image whose pixels are `pixel_size` pixels wide
+pub fn load(file: &str, pixel_size: u32) -> Result<Matrix<Pixel>> {
+    Ok(parse(pixels(file, pixel_size)?))
+}
+
+pub fn run<R: BufRead, W: Write>(file: &str, pixel_size: u32, input: R, out: W) -> Result<Halt> {
+    let program = load(file, pixel_size)?;
+    let mut vm = VM::new(input, out);
+    Ok(vm.execute(program)?)
 }
@@ -1,11 +1,39 @@
-use clap::Parser as ClapParser;
+use anyhow::Result;
+use clap::{Args as ClapArgs, Parser as ClapParser, Subcommand};
+use image::RgbaImage;
+use quilt_lang::{
+    debugger, ArithmeticMode, ErrorKind, Halt, InstructionSet, OutputMode, QuiltError, TapeSize,
+    VMBuilder, DEFAULT_CALL_DEPTH,
+};
 
-use std::io;
+use std::env;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Run a quilt program
 #[derive(ClapParser)]
 #[clap(about, version, author)]
 pub struct Args {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a quilt program. `quilt <FILE>` is short for `quilt run <FILE>`
+    Run(RunArgs),
+    /// Step through a quilt program interactively
+    Debug(DebugArgs),
+    /// Print the instruction and condition tables as markdown
+    Instructions(InstructionsArgs),
+}
+
+#[derive(ClapArgs)]
+struct RunArgs {
     /// A quilt program
     file: String,
 
@@ -16,13 +44,228 @@ pub struct Args {
     /// Open the source file in the editor
     #[clap(short, long)]
     edit: bool,
+
+    #[clap(flatten)]
+    options: VmOptions,
+
+    /// Write a JSON Lines record of every executed instruction to this file
+    #[clap(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Save the program as an image once it stops, with any pixels it
+    /// changed with PUT, one image pixel per program pixel
+    #[clap(long, value_name = "FILE")]
+    save_grid: Option<PathBuf>,
+}
+
+#[derive(ClapArgs)]
+struct DebugArgs {
+    /// A quilt program
+    file: String,
+
+    /// Pixel size
+    #[clap(short, long, default_value_t = 1)]
+    pixel_size: u8,
+
+    #[clap(flatten)]
+    options: VmOptions,
+
+    /// Read the program's input from this file. Without it, the program
+    /// reads nothing, since the debugger's commands come from stdin.
+    #[clap(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+}
+
+#[derive(ClapArgs)]
+struct InstructionsArgs {
+    /// Print this instruction set instead of the standard one
+    #[clap(long, value_name = "FILE")]
+    instruction_set: Option<PathBuf>,
+}
+
+/// Options shared by every subcommand that runs a program
+#[derive(ClapArgs)]
+struct VmOptions {
+    /// Number of tape cells, or `grow` for a tape that grows as it's written to
+    #[clap(long, default_value_t = TapeSize::default())]
+    tape_size: TapeSize,
+
+    /// What arithmetic does on overflow: checked (stop with an error),
+    /// wrapping or saturating
+    #[clap(long, default_value_t = ArithmeticMode::default())]
+    arithmetic: ArithmeticMode,
+
+    /// How OUTPUT writes a value: legacy (the low 8 bits as a Latin-1
+    /// character), bytes (the low 8 bits as a byte) or unicode (a code point)
+    #[clap(long, default_value_t = OutputMode::default())]
+    output: OutputMode,
+
+    /// Seed for RANDOM, so a run can be repeated. Without it every run gets
+    /// different numbers.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// POP UNTIL and OUTPUT UNTIL turn clockwise when the stack runs out
+    /// instead of halting, and can keep the value that stopped them
+    #[clap(long)]
+    loop_exits: bool,
+
+    /// Let lighter pixels repeat their instruction, and less saturated ones
+    /// read their operand from the tape
+    #[clap(long)]
+    extended: bool,
+
+    /// Stop with an error if the program gets stuck in a loop
+    #[clap(long)]
+    detect_loops: bool,
+
+    /// Start a thread at every START pixel, and allow SPAWN
+    #[clap(long)]
+    concurrent: bool,
+
+    /// How many CALLs can be waiting to RETURN at once
+    #[clap(long, value_name = "DEPTH", default_value_t = DEFAULT_CALL_DEPTH)]
+    call_depth: usize,
+
+    /// Read which hue means which instruction from this TOML file
+    #[clap(long, value_name = "FILE")]
+    instruction_set: Option<PathBuf>,
+}
+
+impl VmOptions {
+    fn builder(&self) -> Result<VMBuilder> {
+        Ok(VMBuilder::new()
+            .tape_size(self.tape_size)
+            .arithmetic(self.arithmetic)
+            .output(self.output)
+            .seed(self.seed.unwrap_or_else(time_seed))
+            .loop_exits(self.loop_exits)
+            .extended(self.extended)
+            .detect_loops(self.detect_loops)
+            .concurrent(self.concurrent)
+            .call_depth(self.call_depth)
+            .instruction_set(load_instruction_set(&self.instruction_set)?))
+    }
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+fn load_instruction_set(path: &Option<PathBuf>) -> Result<InstructionSet> {
+    match path {
+        Some(path) => InstructionSet::load(path),
+        None => Ok(InstructionSet::default()),
+    }
+}
+
+/// Exit status for each kind of runtime error. Failing to load the program
+/// at all exits with 100. These start above 99, the highest status `HALT`
+/// exits with, so a program's own code is never mistaken for an error.
+fn exit_code(kind: &ErrorKind) -> i32 {
+    match kind {
+        // `VM::execute` turns a stack underflow into a halt, so only a
+        // missing argument ever gets here
+        ErrorKind::StackUnderflow | ErrorKind::MissingArgument => 101,
+        ErrorKind::DivisionByZero => 102,
+        ErrorKind::TapeOutOfRange { .. } => 103,
+        ErrorKind::InvalidInput(_) => 104,
+        ErrorKind::Io(_) => 105,
+        ErrorKind::InfiniteLoop => 106,
+        ErrorKind::Overflow => 107,
+        ErrorKind::ThreadsDisabled => 108,
+        ErrorKind::ReturnStackUnderflow => 109,
+        ErrorKind::CallDepthExceeded { .. } => 110,
+        ErrorKind::OutOfBounds { .. } => 111,
+        ErrorKind::UnknownSyscall(_) => 112,
+        ErrorKind::Host(_) => 113,
+        ErrorKind::InvalidCodePoint(_) => 114,
+    }
+}
+
+/// Inserts the `run` subcommand when the first argument isn't a subcommand,
+/// so that `quilt <FILE>` keeps working
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+    let first = args.get(1).and_then(|arg| arg.to_str());
+    let is_command = matches!(
+        first,
+        Some("run" | "debug" | "instructions" | "help" | "-h" | "--help" | "-V" | "--version")
+    );
+    if !is_command {
+        args.insert(1.min(args.len()), "run".into());
+    }
+    args
+}
+
+fn run(args: &RunArgs) -> Result<Halt> {
+    let program = quilt_lang::load(&args.file, args.pixel_size as u32)?;
+    let mut builder = args.options.builder()?;
+    if let Some(path) = &args.trace {
+        builder = builder.trace(BufWriter::new(File::create(path)?));
+    }
+
+    let mut vm = builder.build(io::stdin().lock(), io::stdout());
+    let result = vm.execute(program);
+    if let Some(path) = &args.save_grid {
+        RgbaImage::from(vm.instructions()).save(path)?;
+    }
+    Ok(result?)
+}
+
+fn debug(args: &DebugArgs) -> Result<()> {
+    let program = quilt_lang::load(&args.file, args.pixel_size as u32)?;
+    let input: Box<dyn BufRead> = match &args.input {
+        Some(path) => Box::new(BufReader::new(File::open(path)?)),
+        None => Box::new(io::empty()),
+    };
+    debugger::run(program, args.options.builder()?, input)
+}
+
+fn instructions(args: &InstructionsArgs) -> Result<()> {
+    let set = load_instruction_set(&args.instruction_set)?;
+    print!("{}\n{}", set.instructions_table(), set.conditions_table());
+    Ok(())
 }
 
 fn main() {
-    let args = Args::parse();
-    if args.edit {
-        quilt_lang::editor::run(&args.file, args.pixel_size as u32)
-    } else {
-        quilt_lang::run(&args.file, args.pixel_size as u32, io::stdout());
+    let args = Args::parse_from(with_default_command(env::args_os().collect()));
+    match args.command {
+        Command::Run(args) if args.edit => {
+            match load_instruction_set(&args.options.instruction_set) {
+                Ok(set) => quilt_lang::editor::run(&args.file, args.pixel_size as u32, set),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Run(args) => match run(&args) {
+            Ok(halt) => {
+                // exiting skips flushing stdout
+                let _ = io::stdout().flush();
+                process::exit(halt.status());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                let code = e
+                    .downcast_ref::<QuiltError>()
+                    .map_or(100, |e| exit_code(&e.kind));
+                process::exit(code);
+            }
+        },
+        Command::Debug(args) => {
+            if let Err(e) = debug(&args) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        Command::Instructions(args) => {
+            if let Err(e) = instructions(&args) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
     }
 }
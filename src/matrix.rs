@@ -1,9 +1,10 @@
 use crate::vm::Direction::{self, East, North, South, West};
 use crate::Pixel;
 use image::RgbaImage;
+use serde::Serialize;
 use std::ops::{Index, IndexMut};
 
-#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize)]
 pub struct MatrixPoint(pub usize, pub usize);
 
 impl MatrixPoint {
@@ -64,18 +65,40 @@ impl<T: Copy> IndexMut<MatrixPoint> for Matrix<T> {
 impl From<&Matrix<Pixel>> for RgbaImage {
     fn from(matrix: &Matrix<Pixel>) -> Self {
         let height = matrix.matrix.len();
-        let width = matrix.matrix.get(0).map_or(0, |row| row.len());
+        let width = matrix.matrix.first().map_or(0, |row| row.len());
         let mut img = RgbaImage::new(width as u32, height as u32);
         for y in 0..height {
             for x in 0..width {
                 let pixel = matrix[MatrixPoint(x, y)];
-                img.put_pixel(x as u32, y as u32, pixel.hsl.into());
+                img.put_pixel(x as u32, y as u32, pixel.rgba());
             }
         }
         img
     }
 }
 
+#[cfg(test)]
+impl Matrix<Pixel> {
+    /// A program for tests, made of fully saturated pixels of these hues
+    pub(crate) fn from_hues(hues: Vec<Vec<u16>>) -> Self {
+        let rows = hues
+            .into_iter()
+            .map(|row| row.into_iter().map(|h| (h, 100, 50)).collect());
+        Self::from_hsl(rows.collect())
+    }
+
+    /// A program for tests, with the saturation and lightness of every pixel
+    pub(crate) fn from_hsl(pixels: Vec<Vec<(u16, u8, u8)>>) -> Self {
+        let rows = pixels.into_iter().enumerate().map(|(y, row)| {
+            row.into_iter()
+                .enumerate()
+                .map(|(x, (h, s, l))| Pixel::new(crate::Hsl { h, s, l }, MatrixPoint(x, y)))
+                .collect()
+        });
+        Matrix::new(rows.collect())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::vm::Direction;
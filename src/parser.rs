@@ -1,18 +1,17 @@
-use crate::hsl::Hsl;
 use crate::{Matrix, MatrixPoint, Pixel};
 use anyhow::Result;
 use image::io::Reader as ImageReader;
-use image::GenericImageView;
+use image::{GenericImageView, Rgba};
 use std::path::PathBuf;
 
-pub fn pixels<P: Into<PathBuf>>(path: P, size: u32) -> Result<Vec<(u32, u32, Hsl)>> {
+pub fn pixels<P: Into<PathBuf>>(path: P, size: u32) -> Result<Vec<(u32, u32, Rgba<u8>)>> {
     Ok(ImageReader::open(path.into())?
         .decode()?
         .pixels()
         .step_by(size as usize) // this skips horizontally, below we do it vertically.
         .filter_map(|(x, y, px)| {
             if y % size == 0 {
-                Some((x / size, y / size, px.into()))
+                Some((x / size, y / size, px))
             } else {
                 None
             }
@@ -20,7 +19,7 @@ pub fn pixels<P: Into<PathBuf>>(path: P, size: u32) -> Result<Vec<(u32, u32, Hsl
         .collect())
 }
 
-pub fn parse(pixels: Vec<(u32, u32, Hsl)>) -> Matrix<Pixel> {
+pub fn parse(pixels: Vec<(u32, u32, Rgba<u8>)>) -> Matrix<Pixel> {
     let mut rows = vec![];
     let mut row: Vec<Pixel> = vec![];
     let mut prev_y = 0;
@@ -30,7 +29,7 @@ pub fn parse(pixels: Vec<(u32, u32, Hsl)>) -> Matrix<Pixel> {
             prev_y = y;
             row = vec![];
         }
-        row.push(Pixel::new(p, MatrixPoint(x as usize, y as usize)));
+        row.push(Pixel::from_rgb(p, MatrixPoint(x as usize, y as usize)));
     }
     rows.push(row);
     Matrix::new(rows)
@@ -39,8 +38,9 @@ pub fn parse(pixels: Vec<(u32, u32, Hsl)>) -> Matrix<Pixel> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::Hsl;
 
-    fn create_pixels(v: Vec<u16>, width: usize, height: usize) -> Vec<(u32, u32, Hsl)> {
+    fn create_pixels(v: Vec<u16>, width: usize, height: usize) -> Vec<(u32, u32, Rgba<u8>)> {
         let mut out = vec![];
         let mut iter = v.iter();
         for h in 0..height {
@@ -51,8 +51,9 @@ mod test {
                     Hsl {
                         h: *iter.next().unwrap(),
                         s: 100,
-                        l: 100,
-                    },
+                        l: 50,
+                    }
+                    .into(),
                 ));
             }
         }
@@ -1,45 +1,51 @@
-use crate::{Condition, Hsl, Instruction, MatrixPoint};
+use crate::{Condition, Hsl, Instruction, InstructionSet, MatrixPoint};
+
+use image::Rgba;
 
 pub const START: u16 = 300;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Pixel {
     pub hsl: Hsl,
+    /// The exact color, which `hsl` only approximates
+    pub rgb: [u8; 3],
     pub point: MatrixPoint,
 }
 
 impl Pixel {
     pub fn new(hsl: Hsl, point: MatrixPoint) -> Pixel {
-        Pixel { hsl, point }
+        let Rgba([r, g, b, _]) = hsl.into();
+        Pixel {
+            hsl,
+            rgb: [r, g, b],
+            point,
+        }
     }
 
-    pub fn as_instruction(&self) -> Instruction {
-        match self.value() {
-            0..=8 => Instruction::PushA,
-            18..=26 => Instruction::PopUntil,
-            36..=44 => Instruction::Push,
-            54..=62 => Instruction::Save,
-            72..=80 => Instruction::MovA,
-            90..=98 => Instruction::PopA,
-            108..=116 => Instruction::Add,
-            126..=134 => Instruction::Sub,
-            144..=152 => Instruction::Mult,
-            162..=170 => Instruction::Div,
-            180..=188 => Instruction::Road,
-            198..=206 => Instruction::LeftShift,
-            216..=224 => Instruction::RightShift,
-            234..=242 => Instruction::And,
-            252..=260 => Instruction::Or,
-            270..=278 => Instruction::Not,
-            288..=296 => Instruction::Xor,
-            306..=314 => Instruction::Output,
-            324..=332 => Instruction::OutputUntil,
-            342..=350 => Instruction::Modulo,
-            START => Instruction::Start,
-            _ => Instruction::None,
+    pub fn from_rgb(rgb: Rgba<u8>, point: MatrixPoint) -> Pixel {
+        let Rgba([r, g, b, _]) = rgb;
+        Pixel {
+            hsl: rgb.into(),
+            rgb: [r, g, b],
+            point,
         }
     }
 
+    pub fn rgba(&self) -> Rgba<u8> {
+        let [r, g, b] = self.rgb;
+        Rgba([r, g, b, 0xff])
+    }
+
+    /// Changes the hue, keeping saturation and lightness
+    pub fn set_hue(&mut self, hue: u16) {
+        *self = Pixel::new(Hsl { h: hue, ..self.hsl }, self.point);
+    }
+
+    /// The instruction this pixel stands for in the standard instruction set
+    pub fn as_instruction(&self) -> Instruction {
+        InstructionSet::standard().instruction(self.value())
+    }
+
     #[inline]
     pub fn value(&self) -> u16 {
         self.hsl.h
@@ -49,14 +55,25 @@ impl Pixel {
         self.value()
     }
 
-    pub fn as_condition(&self) -> Condition {
-        match self.value() {
-            0..=8 => Condition::NotEqual,
-            72..=80 => Condition::Less,
-            144..=152 => Condition::LessEqual,
-            216..=224 => Condition::Greater,
-            288..=296 => Condition::GreaterEqual,
-            _ => Condition::Equal,
+    /// The whole color as a 24-bit two's complement number, with red as the
+    /// most significant byte
+    pub fn as_wide_data(&self) -> i64 {
+        let [r, g, b] = self.rgb.map(i64::from);
+        let value = r << 16 | g << 8 | b;
+        if value >= 1 << 23 {
+            value - (1 << 24)
+        } else {
+            value
         }
     }
+
+    /// Black, white or any gray in between
+    pub fn is_gray(&self) -> bool {
+        self.hsl.s == 0
+    }
+
+    /// The condition this pixel stands for in the standard instruction set
+    pub fn as_condition(&self) -> Condition {
+        InstructionSet::standard().condition(self.value())
+    }
 }
@@ -0,0 +1,139 @@
+use crate::ErrorKind;
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+type Result<T> = std::result::Result<T, ErrorKind>;
+
+/// What arithmetic instructions do when the result doesn't fit in an `i64`.
+/// Dividing by zero is always a runtime error.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ArithmeticMode {
+    /// Stop with [`ErrorKind::Overflow`]
+    Checked,
+    /// Wrap around in two's complement
+    #[default]
+    Wrapping,
+    /// Clamp to `i64::MIN` or `i64::MAX`
+    Saturating,
+}
+
+impl ArithmeticMode {
+    pub fn add(self, a: i64, b: i64) -> Result<i64> {
+        self.apply(a.checked_add(b), a.wrapping_add(b), a.saturating_add(b))
+    }
+
+    pub fn sub(self, a: i64, b: i64) -> Result<i64> {
+        self.apply(a.checked_sub(b), a.wrapping_sub(b), a.saturating_sub(b))
+    }
+
+    pub fn mul(self, a: i64, b: i64) -> Result<i64> {
+        self.apply(a.checked_mul(b), a.wrapping_mul(b), a.saturating_mul(b))
+    }
+
+    pub fn div(self, a: i64, b: i64) -> Result<i64> {
+        if b == 0 {
+            return Err(ErrorKind::DivisionByZero);
+        }
+        self.apply(a.checked_div(b), a.wrapping_div(b), a.saturating_div(b))
+    }
+
+    pub fn rem(self, a: i64, b: i64) -> Result<i64> {
+        if b == 0 {
+            return Err(ErrorKind::DivisionByZero);
+        }
+        // i64::MIN % -1 is the only overflow, and is 0 in both other modes
+        self.apply(a.checked_rem(b), a.wrapping_rem(b), a.wrapping_rem(b))
+    }
+
+    /// Shifts left by one, which overflows exactly when doubling does
+    pub fn shift_left(self, a: i64) -> Result<i64> {
+        self.mul(a, 2)
+    }
+
+    fn apply(self, checked: Option<i64>, wrapping: i64, saturating: i64) -> Result<i64> {
+        match self {
+            ArithmeticMode::Checked => checked.ok_or(ErrorKind::Overflow),
+            ArithmeticMode::Wrapping => Ok(wrapping),
+            ArithmeticMode::Saturating => Ok(saturating),
+        }
+    }
+}
+
+impl FromStr for ArithmeticMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "checked" => Ok(ArithmeticMode::Checked),
+            "wrapping" => Ok(ArithmeticMode::Wrapping),
+            "saturating" => Ok(ArithmeticMode::Saturating),
+            _ => Err(format!(
+                "unknown arithmetic mode `{}`, expected checked, wrapping or saturating",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for ArithmeticMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithmeticMode::Checked => write!(f, "checked"),
+            ArithmeticMode::Wrapping => write!(f, "wrapping"),
+            ArithmeticMode::Saturating => write!(f, "saturating"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ArithmeticMode::{self, Checked, Saturating, Wrapping};
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_overflow() {
+        assert!(matches!(Checked.add(i64::MAX, 1), Err(ErrorKind::Overflow)));
+        assert_eq!(Wrapping.add(i64::MAX, 1).unwrap(), i64::MIN);
+        assert_eq!(Saturating.add(i64::MAX, 1).unwrap(), i64::MAX);
+
+        assert!(matches!(Checked.sub(i64::MIN, 1), Err(ErrorKind::Overflow)));
+        assert_eq!(Saturating.mul(i64::MIN, 2).unwrap(), i64::MIN);
+
+        assert!(matches!(
+            Checked.div(i64::MIN, -1),
+            Err(ErrorKind::Overflow)
+        ));
+        assert_eq!(Wrapping.div(i64::MIN, -1).unwrap(), i64::MIN);
+        assert_eq!(Saturating.div(i64::MIN, -1).unwrap(), i64::MAX);
+        assert_eq!(Saturating.rem(i64::MIN, -1).unwrap(), 0);
+
+        assert!(matches!(
+            Checked.shift_left(1 << 62),
+            Err(ErrorKind::Overflow)
+        ));
+        assert_eq!(Wrapping.shift_left(1 << 62).unwrap(), i64::MIN);
+        assert_eq!(Saturating.shift_left(-(1 << 62) - 1).unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        for mode in [Checked, Wrapping, Saturating] {
+            assert!(matches!(mode.div(1, 0), Err(ErrorKind::DivisionByZero)));
+            assert!(matches!(mode.rem(1, 0), Err(ErrorKind::DivisionByZero)));
+        }
+    }
+
+    #[test]
+    fn test_in_range() {
+        for mode in [Checked, Wrapping, Saturating] {
+            assert_eq!(mode.add(2, 3).unwrap(), 5);
+            assert_eq!(mode.sub(2, 3).unwrap(), -1);
+            assert_eq!(mode.mul(-2, 3).unwrap(), -6);
+            assert_eq!(mode.div(-7, 2).unwrap(), -3);
+            assert_eq!(mode.rem(-7, 2).unwrap(), -1);
+            assert_eq!(mode.shift_left(-3).unwrap(), -6);
+        }
+        assert_eq!("saturating".parse(), Ok(ArithmeticMode::Saturating));
+    }
+}
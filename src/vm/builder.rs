@@ -0,0 +1,209 @@
+use super::cycle::CycleDetector;
+use super::history::History;
+use super::modifiers::Modifiers;
+use super::random::{Random, DEFAULT_SEED};
+use super::trace::Tracer;
+use super::{
+    ArithmeticMode, Direction, Host, NoHost, OutputMode, Tape, TapeSize, DEFAULT_CALL_DEPTH, VM,
+};
+use crate::{InstructionSet, Matrix, MatrixPoint};
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+/// Configures a [`VM`] before it is created
+///
+/// ```
+/// use quilt_lang::VMBuilder;
+/// use std::io;
+///
+/// let vm = VMBuilder::new()
+///     .detect_loops(true)
+///     .build(io::empty(), io::sink());
+/// ```
+pub struct VMBuilder<H: Host = NoHost> {
+    tape_size: TapeSize,
+    arithmetic: ArithmeticMode,
+    output: OutputMode,
+    seed: u64,
+    loop_exits: bool,
+    extended: bool,
+    detect_loops: bool,
+    concurrent: bool,
+    call_depth: usize,
+    history: usize,
+    trace: Option<Box<dyn Write>>,
+    instruction_set: InstructionSet,
+    host: H,
+}
+
+impl Default for VMBuilder {
+    fn default() -> Self {
+        VMBuilder {
+            tape_size: TapeSize::default(),
+            arithmetic: ArithmeticMode::default(),
+            output: OutputMode::default(),
+            seed: DEFAULT_SEED,
+            loop_exits: false,
+            extended: false,
+            detect_loops: false,
+            concurrent: false,
+            call_depth: DEFAULT_CALL_DEPTH,
+            history: 0,
+            trace: None,
+            instruction_set: InstructionSet::default(),
+            host: NoHost,
+        }
+    }
+}
+
+impl VMBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<H: Host> VMBuilder<H> {
+    /// How many cells the tape has. Defaults to a fixed 360.
+    pub fn tape_size(mut self, tape_size: TapeSize) -> Self {
+        self.tape_size = tape_size;
+        self
+    }
+
+    /// How arithmetic instructions handle overflow. Defaults to wrapping.
+    pub fn arithmetic(mut self, arithmetic: ArithmeticMode) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    /// How values are written as characters. Defaults to the legacy mode,
+    /// which writes the low 8 bits as a Latin-1 character.
+    pub fn output(mut self, output: OutputMode) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Where `RANDOM` starts its sequence of numbers, which is the same every
+    /// run with the same seed. Defaults to [`DEFAULT_SEED`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Change how `POP UNTIL` and `OUTPUT UNTIL` finish. Running out of
+    /// values turns the program clockwise instead of halting it, and a `PUSH`
+    /// pixel at a loop's back-left corner keeps the value that met the
+    /// condition on the stack.
+    pub fn loop_exits(mut self, loop_exits: bool) -> Self {
+        self.loop_exits = loop_exits;
+        self
+    }
+
+    /// Let saturation and lightness modify instructions. Pixels at least 60%
+    /// light run their instruction once more for every 10% above 50%, and
+    /// arithmetic and output pixels less than 50% saturated take the value
+    /// they would pop first from `tape[A]` instead. Off by default, where
+    /// only hue matters.
+    pub fn extended(mut self, extended: bool) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    /// Stop with [`ErrorKind::InfiniteLoop`](crate::ErrorKind::InfiniteLoop)
+    /// when the machine returns to a state it has already been in
+    pub fn detect_loops(mut self, detect_loops: bool) -> Self {
+        self.detect_loops = detect_loops;
+        self
+    }
+
+    /// Start a thread at every `START` pixel rather than only the first, and
+    /// allow `SPAWN` to start more. Threads take turns running one
+    /// instruction each, in the order they started, and share the tape.
+    pub fn concurrent(mut self, concurrent: bool) -> Self {
+        self.concurrent = concurrent;
+        self
+    }
+
+    /// How many `CALL`s can be waiting to `RETURN` at once, in each thread.
+    /// Defaults to [`DEFAULT_CALL_DEPTH`].
+    pub fn call_depth(mut self, call_depth: usize) -> Self {
+        self.call_depth = call_depth;
+        self
+    }
+
+    /// Remember how to undo up to this many of the most recent steps, for
+    /// [`VM::step_back`]. Defaults to 0, which keeps no history at all.
+    pub fn history(mut self, steps: usize) -> Self {
+        self.history = steps;
+        self
+    }
+
+    /// Write a JSON Lines record of every executed instruction to `out`
+    pub fn trace<T: Write + 'static>(mut self, out: T) -> Self {
+        self.trace = Some(Box::new(out));
+        self
+    }
+
+    /// Which hue means which instruction and condition. Defaults to
+    /// [`InstructionSet::standard`].
+    pub fn instruction_set(mut self, instruction_set: InstructionSet) -> Self {
+        self.instruction_set = instruction_set;
+        self
+    }
+
+    /// Where `SYSCALL`s go. Defaults to [`NoHost`], which has no functions.
+    pub fn host<T: Host>(self, host: T) -> VMBuilder<T> {
+        VMBuilder {
+            tape_size: self.tape_size,
+            arithmetic: self.arithmetic,
+            output: self.output,
+            seed: self.seed,
+            loop_exits: self.loop_exits,
+            extended: self.extended,
+            detect_loops: self.detect_loops,
+            concurrent: self.concurrent,
+            call_depth: self.call_depth,
+            history: self.history,
+            trace: self.trace,
+            instruction_set: self.instruction_set,
+            host,
+        }
+    }
+
+    pub fn build<R: BufRead, W: Write>(self, input: R, out: W) -> VM<R, W, H> {
+        VM {
+            stack: vec![],
+            register_a: 0,
+            calls: vec![],
+            call_depth: self.call_depth,
+            tape: Tape::new(self.tape_size),
+            arithmetic: self.arithmetic,
+            output: self.output,
+            seed: self.seed,
+            rng: Random::new(self.seed),
+            loop_exits: self.loop_exits,
+            extended: self.extended,
+            modifiers: Modifiers::default(),
+            direction: Direction::East,
+            instruction_set: self.instruction_set,
+            instructions: Matrix::new(vec![]),
+            graph: None,
+            pc: MatrixPoint(0, 0),
+            steps: 0,
+            concurrent: self.concurrent,
+            thread: 0,
+            threads: VecDeque::new(),
+            next_thread: 1,
+            halted: None,
+            exit: None,
+            cycles: self.detect_loops.then(CycleDetector::default),
+            tracer: self.trace.map(Tracer::new),
+            tape_writes: vec![],
+            history: (self.history > 0).then(|| History::new(self.history)),
+            undo: None,
+            input,
+            out,
+            host: self.host,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::random::Random;
+use super::thread::Thread;
+use super::{Direction, Host, Tape, VM};
+use crate::MatrixPoint;
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+/// Everything that decides what the machine does next, apart from its input
+#[derive(Clone, Debug, PartialEq)]
+struct State {
+    pc: MatrixPoint,
+    direction: Direction,
+    register_a: u16,
+    stack: Vec<i64>,
+    tape: Tape,
+    calls: Vec<(MatrixPoint, Direction)>,
+    threads: VecDeque<Thread>,
+    rng: Random,
+}
+
+impl State {
+    fn of<R: BufRead, W: Write, H: Host>(vm: &VM<R, W, H>) -> Self {
+        State {
+            pc: vm.pc,
+            direction: vm.direction,
+            register_a: vm.register_a,
+            stack: vm.stack.clone(),
+            tape: vm.tape.clone(),
+            calls: vm.calls.clone(),
+            threads: vm.threads.clone(),
+            rng: vm.rng,
+        }
+    }
+
+    // compares the cheap fields first, so most steps never look at the stack
+    fn matches<R: BufRead, W: Write, H: Host>(&self, vm: &VM<R, W, H>) -> bool {
+        self.pc == vm.pc
+            && self.direction == vm.direction
+            && self.register_a == vm.register_a
+            && self.stack == vm.stack
+            && self.tape == vm.tape
+            && self.calls == vm.calls
+            && self.threads == vm.threads
+            && self.rng == vm.rng
+    }
+}
+
+/// Brent's cycle detection over machine states. Only one earlier state is
+/// kept at a time, and states are compared exactly, so a program is never
+/// reported as looping unless it really would run forever.
+#[derive(Debug, Default)]
+pub(super) struct CycleDetector {
+    saved: Option<State>,
+    power: u64,
+    length: u64,
+}
+
+impl CycleDetector {
+    /// Returns true once the machine is back in a state it has been in before
+    pub fn observe<R: BufRead, W: Write, H: Host>(&mut self, vm: &VM<R, W, H>) -> bool {
+        if let Some(saved) = &self.saved {
+            if saved.matches(vm) {
+                return true;
+            }
+        }
+
+        if self.length == self.power {
+            self.saved = Some(State::of(vm));
+            self.power = (self.power * 2).max(1);
+            self.length = 0;
+        }
+        self.length += 1;
+
+        false
+    }
+
+    /// Forgets every state seen so far, e.g. after the machine reads input
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
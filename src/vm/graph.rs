@@ -0,0 +1,290 @@
+use super::Direction::{self, East, North, South, West};
+use crate::{Matrix, MatrixPoint, Pixel};
+
+use std::collections::{HashMap, HashSet};
+
+// in the order of `Direction`'s discriminants
+const DIRECTIONS: [Direction; 4] = [North, East, South, West];
+
+// what an entry of `Graph::next` holds, besides 2 more than the index of the
+// pixel and heading it leads to
+const UNKNOWN: u32 = 0;
+const NOWHERE: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Skip {
+    /// The roads lead around in a circle, so there's nothing to skip to
+    Circle,
+    /// The index of the first pixel and heading that isn't a road, and the
+    /// number of roads on the way there
+    To(u32, u32),
+}
+
+/// Where the program counter goes from every pixel, heading every way. Each
+/// way is only worked out the first time the program counter goes there,
+/// rather than on every step, and again after the program changes a pixel
+/// next to it.
+///
+/// A pixel and heading is kept as its index, `(y * width + x) * 4` plus the
+/// heading, which keeps the graph to a few bytes per pixel.
+#[derive(Debug)]
+pub(super) struct Graph {
+    width: usize,
+    next: Vec<u32>,
+    /// The first pixel that isn't a road from each pixel and heading the
+    /// roads have been followed from, filled in as the program runs
+    skip: HashMap<u32, Skip>,
+}
+
+impl Graph {
+    /// An empty graph for the program, or `None` if it has too many pixels
+    /// to index
+    pub fn new(instructions: &Matrix<Pixel>) -> Option<Self> {
+        let width = instructions.matrix.iter().map(Vec::len).max().unwrap_or(0);
+        let len = width
+            .checked_mul(instructions.matrix.len())?
+            .checked_mul(4)?;
+        if len > (u32::MAX - 2) as usize {
+            return None;
+        }
+
+        Some(Graph {
+            width,
+            // zeroed, so the pages aren't touched until they're decoded
+            next: vec![UNKNOWN; len],
+            skip: HashMap::new(),
+        })
+    }
+
+    /// Forgets where `points` lead, after their pixels or their neighbours
+    /// have changed. Any road could lead somewhere else now, so roads are
+    /// followed again the next time they're skipped.
+    pub fn update(&mut self, points: &[MatrixPoint]) {
+        for &point in points {
+            for direction in DIRECTIONS {
+                if let Some(index) = self.index(point, direction) {
+                    self.next[index as usize] = UNKNOWN;
+                }
+            }
+        }
+        self.skip.clear();
+    }
+
+    fn index(&self, point: MatrixPoint, direction: Direction) -> Option<u32> {
+        let index = (point.1 * self.width + point.0) * 4 + direction as usize;
+        (point.0 < self.width && index < self.next.len()).then_some(index as u32)
+    }
+
+    fn at(&self, index: u32) -> (Direction, MatrixPoint) {
+        let point = index as usize / 4;
+        let point = MatrixPoint(point % self.width, point / self.width);
+        (DIRECTIONS[index as usize % 4], point)
+    }
+
+    // the index that the pixel and heading at `index` leads to, decoding it
+    // the first time
+    fn follow<F>(&mut self, index: u32, decode: F) -> Option<u32>
+    where
+        F: FnOnce(MatrixPoint, Direction) -> Option<(Direction, MatrixPoint)>,
+    {
+        if self.next[index as usize] == UNKNOWN {
+            let (direction, point) = self.at(index);
+            let next = decode(point, direction)
+                .and_then(|(direction, point)| self.index(point, direction))
+                .map_or(NOWHERE, |next| next + 2);
+            self.next[index as usize] = next;
+        }
+
+        match self.next[index as usize] {
+            NOWHERE => None,
+            next => Some(next - 2),
+        }
+    }
+
+    /// `decode` gives the next pixel from a point and heading, and is only
+    /// called the first time the program counter goes that way
+    pub fn next<F>(
+        &mut self,
+        point: MatrixPoint,
+        direction: Direction,
+        decode: F,
+    ) -> Option<(Direction, MatrixPoint)>
+    where
+        F: FnOnce(MatrixPoint, Direction) -> Option<(Direction, MatrixPoint)>,
+    {
+        match self.index(point, direction) {
+            Some(index) => self.follow(index, decode).map(|next| self.at(next)),
+            None => decode(point, direction),
+        }
+    }
+
+    /// Follows the roads ahead to the first pixel that isn't one, returning
+    /// it along with the direction the program counter is heading when it
+    /// gets there and the number of roads on the way. `is_road` tells
+    /// whether the pixel at a point is a road.
+    ///
+    /// Every road passed on the way is remembered too, so each road is only
+    /// ever followed once.
+    pub fn skip_roads<F, R>(
+        &mut self,
+        point: MatrixPoint,
+        direction: Direction,
+        decode: F,
+        is_road: R,
+    ) -> Option<(Direction, MatrixPoint, u64)>
+    where
+        F: Fn(MatrixPoint, Direction) -> Option<(Direction, MatrixPoint)>,
+        R: Fn(MatrixPoint) -> bool,
+    {
+        let index = self.index(point, direction)?;
+        if let Some(&skip) = self.skip.get(&index) {
+            return self.skipped(skip);
+        }
+
+        // the road pixels passed, with the heading they were passed in
+        let mut path = vec![index];
+        let mut visited = HashSet::from([index]);
+        let mut at = self.follow(index, &decode)?;
+        let skip = loop {
+            if !is_road(self.at(at).1) {
+                break Skip::To(at, 0);
+            }
+            // the same road in the same heading again means a circle
+            if !visited.insert(at) {
+                break Skip::Circle;
+            }
+            path.push(at);
+            if let Some(&skip) = self.skip.get(&at) {
+                break skip;
+            }
+            at = self.follow(at, &decode)?;
+        };
+
+        // each step back along the path is one more road to pass
+        let last = path.len() - 1;
+        for (i, &at) in path.iter().enumerate() {
+            let skip = match skip {
+                Skip::To(to, roads) => Skip::To(to, roads + (last - i) as u32),
+                skip => skip,
+            };
+            self.skip.insert(at, skip);
+        }
+
+        self.skipped(self.skip[&index])
+    }
+
+    fn skipped(&self, skip: Skip) -> Option<(Direction, MatrixPoint, u64)> {
+        match skip {
+            Skip::To(to, roads) => {
+                let (direction, point) = self.at(to);
+                Some((direction, point, roads.into()))
+            }
+            Skip::Circle => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Skip, UNKNOWN};
+    use crate::vm::Direction::{East, North, South, West};
+    use crate::vm::VM;
+    use crate::{Matrix, MatrixPoint};
+
+    use std::io;
+
+    #[test]
+    fn test_skip_roads_remembers_path() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![300, 180, 180, 36, 1]]));
+
+        let pixel = vm.skip_roads();
+        assert_eq!((pixel.point, vm.steps()), (MatrixPoint(3, 0), 2));
+        let graph = vm.graph.as_ref().unwrap();
+        let index = graph.index(MatrixPoint(1, 0), East).unwrap();
+        assert!(matches!(graph.skip[&index], Skip::To(_, 1)));
+        let index = graph.index(MatrixPoint(2, 0), East).unwrap();
+        assert!(matches!(graph.skip[&index], Skip::To(_, 0)));
+    }
+
+    #[test]
+    fn test_skip_roads_circle() {
+        // the road from START leads into a circle
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![
+            vec![13, 180, 180, 180],
+            vec![300, 180, 13, 180],
+            vec![13, 180, 180, 180],
+        ]));
+
+        // there's nothing to skip to, so it only goes one pixel
+        assert_eq!(vm.skip_roads().point, MatrixPoint(1, 1));
+        assert_eq!(vm.steps(), 0);
+
+        // every road of the circle is known to be part of it, however it's
+        // reached later
+        let graph = vm.graph.as_ref().unwrap();
+        for (point, direction) in [
+            (MatrixPoint(1, 2), South),
+            (MatrixPoint(3, 1), North),
+            (MatrixPoint(1, 0), West),
+        ] {
+            let index = graph.index(point, direction).unwrap();
+            assert_eq!(graph.skip[&index], Skip::Circle);
+        }
+        let circles = graph.skip.values().filter(|&&skip| skip == Skip::Circle);
+        assert_eq!(circles.count(), 10);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![
+            vec![300, 180, 13, 180, 36, 1],
+            vec![13, 13, 13, 13, 13, 13],
+        ]));
+        let skip = |vm: &mut VM<_, _>| {
+            let steps = vm.steps();
+            let pixel = vm.skip_roads();
+            vm.pc = MatrixPoint(0, 0);
+            vm.direction = East;
+            (pixel.point, vm.steps() - steps)
+        };
+        assert_eq!(skip(&mut vm), (MatrixPoint(2, 0), 1));
+
+        // turning the data into a road joins up the roads either side
+        vm.instructions[MatrixPoint(2, 0)].set_hue(180);
+        vm.update_graph(MatrixPoint(2, 0));
+        assert_eq!(skip(&mut vm), (MatrixPoint(4, 0), 3));
+
+        // everything the graph still knows matches the program as it is now
+        let mut graph = vm.graph.take().unwrap();
+        for y in 0..2 {
+            for x in 0..6 {
+                for direction in [North, East, South, West] {
+                    let point = MatrixPoint(x, y);
+                    let index = graph.index(point, direction).unwrap();
+                    if graph.next[index as usize] != UNKNOWN {
+                        let known = graph.next(point, direction, |_, _| unreachable!());
+                        let decoded = vm.decode_next(point, direction);
+                        assert_eq!(known, decoded, "{:?} {:?}", point, direction);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_program() {
+        // a million pixels, of which the program only ever goes through a few
+        let mut hues = vec![vec![13; 1000]; 1000];
+        hues[0][..5].copy_from_slice(&[300, 36, 1, 90, 108]);
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.execute(Matrix::from_hues(hues)).unwrap();
+
+        let graph = vm.graph.as_ref().unwrap();
+        let decoded = graph.next.iter().filter(|&&next| next != UNKNOWN);
+        assert!(decoded.count() <= 4);
+        assert!(graph.skip.len() <= 4);
+    }
+}
@@ -0,0 +1,142 @@
+use super::random::Random;
+use super::thread::Switch;
+use super::{Direction, Tape};
+use crate::{MatrixPoint, Pixel};
+
+use std::collections::VecDeque;
+
+/// Everything a single step changed, so that it can be undone
+#[derive(Debug)]
+pub(super) struct Undo {
+    pub pc: MatrixPoint,
+    pub direction: Direction,
+    pub register_a: u16,
+    /// The lowest the stack got during the step. Everything below it is
+    /// untouched.
+    pub low_water: usize,
+    /// Values popped from below `low_water`, in the order they were popped
+    pub popped: Vec<i64>,
+    pub tape_len: usize,
+    /// `(address, old value)` for each tape write, in the order they happened
+    pub tape_writes: Vec<(usize, i64)>,
+    /// Whether the step started a new thread, at the back of the queue
+    pub spawned: bool,
+    /// Whether the step was a `CALL`
+    pub called: bool,
+    /// Where a `RETURN` returned to
+    pub returned: Option<(MatrixPoint, Direction)>,
+    /// The stack and tape before a `SYSCALL` ran
+    pub snapshot: Option<(Vec<i64>, Tape)>,
+    /// The pixels a `PUT` changed, as they were before, in the order they
+    /// were changed
+    pub grid_writes: Vec<Pixel>,
+    /// The random number generator before a `RANDOM` ran
+    pub rng: Option<Random>,
+    pub switch: Option<Switch>,
+}
+
+impl Undo {
+    pub fn new(
+        pc: MatrixPoint,
+        direction: Direction,
+        register_a: u16,
+        stack_len: usize,
+        tape_len: usize,
+    ) -> Self {
+        Undo {
+            pc,
+            direction,
+            register_a,
+            low_water: stack_len,
+            popped: vec![],
+            tape_len,
+            tape_writes: vec![],
+            spawned: false,
+            called: false,
+            returned: None,
+            snapshot: None,
+            grid_writes: vec![],
+            rng: None,
+            switch: None,
+        }
+    }
+
+    /// Records a pop that left `stack_len` values on the stack
+    pub fn popped(&mut self, value: i64, stack_len: usize) {
+        // values pushed during this step and popped again don't need restoring
+        if stack_len < self.low_water {
+            self.low_water = stack_len;
+            self.popped.push(value);
+        }
+    }
+}
+
+/// The most recent steps, up to a limit
+#[derive(Debug)]
+pub(super) struct History {
+    steps: VecDeque<Undo>,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        History {
+            steps: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub fn push(&mut self, undo: Undo) {
+        if self.steps.len() == self.limit {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(undo);
+    }
+
+    pub fn pop(&mut self) -> Option<Undo> {
+        self.steps.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{History, Undo};
+    use crate::{Direction, MatrixPoint};
+
+    fn undo(register_a: u16) -> Undo {
+        Undo::new(MatrixPoint(0, 0), Direction::East, register_a, 0, 0)
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut history = History::new(2);
+        history.push(undo(1));
+        history.push(undo(2));
+        history.push(undo(3));
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.pop().unwrap().register_a, 3);
+        assert_eq!(history.pop().unwrap().register_a, 2);
+        assert!(history.pop().is_none());
+    }
+
+    #[test]
+    fn test_popped() {
+        let mut undo = Undo::new(MatrixPoint(0, 0), Direction::East, 0, 3, 0);
+        // pop two, push one and pop it again
+        undo.popped(30, 2);
+        undo.popped(20, 1);
+        undo.popped(99, 1);
+
+        assert_eq!(undo.low_water, 1);
+        assert_eq!(undo.popped, vec![30, 20]);
+    }
+}
@@ -0,0 +1,99 @@
+use super::Tape;
+use crate::ErrorKind;
+
+use std::collections::HashMap;
+
+/// Functions a program can call with `SYSCALL`, provided by whatever embeds
+/// the VM. Set with [`VMBuilder::host`](super::VMBuilder::host).
+pub trait Host {
+    /// Runs function `number`, which `SYSCALL` has already popped. Returning
+    /// [`ErrorKind::StackUnderflow`] halts the program like any other
+    /// instruction popping an empty stack, and any other error stops it.
+    fn syscall(&mut self, number: i64, machine: &mut Machine<'_>) -> Result<(), ErrorKind>;
+}
+
+/// The host that provides nothing, so every `SYSCALL` fails with
+/// [`ErrorKind::UnknownSyscall`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoHost;
+
+impl Host for NoHost {
+    fn syscall(&mut self, number: i64, _: &mut Machine<'_>) -> Result<(), ErrorKind> {
+        Err(ErrorKind::UnknownSyscall(number))
+    }
+}
+
+type Callback = Box<dyn FnMut(&mut Machine<'_>) -> Result<(), ErrorKind>>;
+
+/// A host made of callbacks, each registered under its function number
+///
+/// ```
+/// use quilt_lang::{Syscalls, VMBuilder};
+/// use std::io;
+///
+/// let host = Syscalls::new().register(1, |machine| {
+///     let n = machine.pop()?;
+///     machine.push(n * n);
+///     Ok(())
+/// });
+/// let vm = VMBuilder::new().host(host).build(io::empty(), io::sink());
+/// ```
+#[derive(Default)]
+pub struct Syscalls {
+    functions: HashMap<i64, Callback>,
+}
+
+impl Syscalls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` for `SYSCALL`s of function `number`, replacing whatever was
+    /// registered for it before
+    pub fn register<F>(mut self, number: i64, f: F) -> Self
+    where
+        F: FnMut(&mut Machine<'_>) -> Result<(), ErrorKind> + 'static,
+    {
+        self.functions.insert(number, Box::new(f));
+        self
+    }
+}
+
+impl Host for Syscalls {
+    fn syscall(&mut self, number: i64, machine: &mut Machine<'_>) -> Result<(), ErrorKind> {
+        match self.functions.get_mut(&number) {
+            Some(f) => f(machine),
+            None => Err(ErrorKind::UnknownSyscall(number)),
+        }
+    }
+}
+
+/// The parts of the running thread a host function can see and change
+pub struct Machine<'a> {
+    pub(super) stack: &'a mut Vec<i64>,
+    pub(super) tape: &'a mut Tape,
+    pub(super) register_a: u16,
+}
+
+impl Machine<'_> {
+    pub fn push(&mut self, value: i64) {
+        self.stack.push(value);
+    }
+
+    pub fn pop(&mut self) -> Result<i64, ErrorKind> {
+        self.stack.pop().ok_or(ErrorKind::StackUnderflow)
+    }
+
+    /// The whole stack, with the top last
+    pub fn stack(&mut self) -> &mut Vec<i64> {
+        self.stack
+    }
+
+    pub fn tape(&mut self) -> &mut Tape {
+        self.tape
+    }
+
+    pub fn register_a(&self) -> u16 {
+        self.register_a
+    }
+}
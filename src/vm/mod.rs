@@ -0,0 +1,2464 @@
+mod arithmetic;
+mod builder;
+mod cycle;
+mod graph;
+mod history;
+mod host;
+mod modifiers;
+mod output;
+mod random;
+mod tape;
+mod thread;
+mod trace;
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+
+pub use arithmetic::ArithmeticMode;
+pub use builder::VMBuilder;
+use cycle::CycleDetector;
+use graph::Graph;
+use history::{History, Undo};
+pub use host::{Host, Machine, NoHost, Syscalls};
+use modifiers::Modifiers;
+pub use output::OutputMode;
+use random::Random;
+pub use random::DEFAULT_SEED;
+pub use tape::{Tape, TapeSize};
+use thread::{Switch, Thread};
+use trace::{Record, Tracer};
+
+use crate::error::{ErrorKind, Halt, HaltReason, QuiltError};
+use crate::{Condition, Hsl, Instruction, InstructionSet, Pixel};
+use crate::{Matrix, MatrixPoint};
+
+type Result<T> = std::result::Result<T, ErrorKind>;
+
+/// How many `CALL`s can be waiting to `RETURN` at once, unless configured
+/// with [`VMBuilder::call_depth`]
+pub const DEFAULT_CALL_DEPTH: usize = 1000;
+
+/// The most digits a `VAR PUSH` number can have, since 360^8 is more than
+/// an `i64` holds
+const MAX_DIGITS: usize = 8;
+
+pub struct VM<R: BufRead, W: Write, H: Host = NoHost> {
+    stack: Vec<i64>,
+    register_a: u16,
+    /// Where each `CALL` waiting to `RETURN` came from
+    calls: Vec<(MatrixPoint, Direction)>,
+    call_depth: usize,
+    tape: Tape,
+    arithmetic: ArithmeticMode,
+    output: OutputMode,
+    seed: u64,
+    /// Where `RANDOM` gets its numbers, started again from `seed` on load
+    rng: Random,
+    loop_exits: bool,
+    extended: bool,
+    /// How the current instruction's pixel modifies it, in extended mode
+    modifiers: Modifiers,
+    direction: Direction,
+    instruction_set: InstructionSet,
+    instructions: Matrix<Pixel>,
+    graph: Option<Graph>,
+    pc: MatrixPoint,
+    steps: u64,
+    concurrent: bool,
+    /// Id of the running thread
+    thread: usize,
+    /// Every other thread, in the order they'll run
+    threads: VecDeque<Thread>,
+    next_thread: usize,
+    halted: Option<Halt>,
+    /// The exit code given by a `HALT` in the current step
+    exit: Option<i64>,
+    cycles: Option<CycleDetector>,
+    tracer: Option<Tracer>,
+    /// Tape cells written by the current instruction, kept for the trace
+    tape_writes: Vec<(usize, i64)>,
+    history: Option<History>,
+    /// What the current instruction changed, while history is kept
+    undo: Option<Undo>,
+    input: R,
+    out: W,
+    host: H,
+}
+
+/// The result of running a single instruction with [`VM::step`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepOutcome {
+    Running,
+    Halted(Halt),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    pub fn clockwise(&self) -> Direction {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+        }
+    }
+
+    #[inline]
+    pub fn opposite(&self) -> Direction {
+        self.clockwise().clockwise()
+    }
+
+    #[inline]
+    pub fn counter_clockwise(&self) -> Direction {
+        self.opposite().clockwise()
+    }
+}
+
+impl Default for VM<BufReader<io::Stdin>, io::Stdout> {
+    fn default() -> VM<BufReader<io::Stdin>, io::Stdout> {
+        VM::new(BufReader::new(io::stdin()), io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> VM<R, W> {
+    pub fn new(input: R, out: W) -> VM<R, W> {
+        VMBuilder::new().build(input, out)
+    }
+}
+
+impl<R: BufRead, W: Write, H: Host> VM<R, W, H> {
+    /// Runs the program until it halts or hits a runtime error
+    pub fn execute(
+        &mut self,
+        instructions: Matrix<Pixel>,
+    ) -> std::result::Result<Halt, QuiltError> {
+        self.load(instructions);
+
+        loop {
+            let skip_roads = self.can_skip_roads();
+            if let StepOutcome::Halted(halt) = self.run_step(skip_roads)? {
+                return Ok(halt);
+            }
+        }
+    }
+
+    /// Resets the machine and places the program counter on the program's
+    /// `START` pixel, ready for [`VM::step`]
+    pub fn load(&mut self, instructions: Matrix<Pixel>) {
+        self.instructions = instructions;
+        self.graph = Graph::new(&self.instructions);
+        self.stack.clear();
+        self.register_a = 0;
+        self.calls.clear();
+        self.tape = Tape::new(self.tape.size());
+        self.rng = Random::new(self.seed);
+        self.direction = Direction::East;
+        self.pc = self.find_start();
+        self.thread = 0;
+        self.threads.clear();
+        if self.concurrent {
+            for (id, pc) in self.find_starts().into_iter().enumerate().skip(1) {
+                self.threads.push_back(Thread::new(id, pc));
+            }
+        }
+        self.next_thread = self.threads.len() + 1;
+        self.steps = 0;
+        self.halted = None;
+        self.exit = None;
+        if let Some(cycles) = &mut self.cycles {
+            cycles.reset();
+        }
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+
+    // where the program counter goes next depends on the pixels around it,
+    // so a changed pixel only changes where its neighbours lead
+    fn update_graph(&mut self, point: MatrixPoint) {
+        let mut points = vec![point];
+        for direction in [
+            Direction::North,
+            Direction::East,
+            Direction::South,
+            Direction::West,
+        ] {
+            if let Some(pixel) = self.instructions.go(point, direction) {
+                points.push(pixel.point);
+            }
+        }
+
+        if let Some(graph) = &mut self.graph {
+            graph.update(&points);
+        }
+    }
+
+    /// Executes a single instruction, along with fetching its argument and
+    /// condition. Once the program has halted, every further call returns
+    /// the same [`StepOutcome::Halted`] without running anything.
+    ///
+    /// A road is a step of its own, the same as any other instruction.
+    pub fn step(&mut self) -> std::result::Result<StepOutcome, QuiltError> {
+        self.run_step(false)
+    }
+
+    // `execute` passes any roads leading up to the instruction in the same
+    // call when nothing would see them, still counting each one as a step
+    fn run_step(&mut self, skip_roads: bool) -> std::result::Result<StepOutcome, QuiltError> {
+        if let Some(halt) = self.halted {
+            return Ok(StepOutcome::Halted(halt));
+        }
+
+        if self.history.is_some() {
+            self.undo = Some(Undo::new(
+                self.pc,
+                self.direction,
+                self.register_a,
+                self.stack.len(),
+                self.tape.cells().len(),
+            ));
+        }
+
+        let (start_pc, start_direction) = (self.pc, self.direction);
+        let pixel = if skip_roads {
+            self.skip_roads()
+        } else {
+            self.get_next_instruction()
+        };
+        self.pc = pixel.point;
+        self.steps += 1;
+        self.tape_writes.clear();
+        self.exit = None;
+
+        let instruction = self.decode(pixel);
+
+        let condition = if instruction.is_conditional() {
+            self.get_condition()
+        } else {
+            Condition::Equal
+        };
+
+        let arg = if instruction.takes_arg() {
+            let arg_pixel = self.get_next_instruction();
+            self.pc = arg_pixel.point;
+            Some(arg_pixel)
+        } else {
+            None
+        };
+
+        self.modifiers = if self.extended {
+            Modifiers::new(pixel.hsl, instruction)
+        } else {
+            Modifiers::default()
+        };
+        let mut result = self.execute_instruction(instruction, arg, condition);
+        for _ in 1..self.modifiers.repeat {
+            result = result.and_then(|()| self.execute_instruction(instruction, arg, condition));
+        }
+
+        // JOIN stays where it is, giving up its turns, until every other
+        // thread has finished
+        if instruction == Instruction::Join && !self.threads.is_empty() {
+            self.pc = start_pc;
+            self.direction = start_direction;
+        }
+
+        // the failing instruction is traced too, since it's usually the one
+        // that matters most
+        let traced = self.trace(pixel.point, instruction, arg, condition, result.is_err());
+        if result.is_ok() {
+            result = traced;
+        }
+        let result = result.and_then(|()| self.detect_loop(instruction));
+
+        // HALT stops every thread at once. Otherwise popping an empty stack is
+        // how programs exit, or with concurrency, how a thread finishes
+        let outcome = match result {
+            Ok(()) => match self.exit {
+                Some(code) => Ok(self.halt(HaltReason::Instruction(code))),
+                None => {
+                    self.rotate();
+                    Ok(StepOutcome::Running)
+                }
+            },
+            Err(ErrorKind::StackUnderflow) if !self.threads.is_empty() => {
+                self.end_thread();
+                Ok(StepOutcome::Running)
+            }
+            Err(ErrorKind::StackUnderflow) => Ok(self.halt(HaltReason::EmptyStack)),
+            Err(kind) => Err(QuiltError {
+                kind,
+                pc: self.pc,
+                direction: self.direction,
+                instruction,
+            }),
+        };
+
+        // failed steps are kept as well, so a debugger can step back from the
+        // error
+        if let (Some(history), Some(undo)) = (&mut self.history, self.undo.take()) {
+            history.push(undo);
+        }
+        outcome
+    }
+
+    fn halt(&mut self, reason: HaltReason) -> StepOutcome {
+        let halt = Halt {
+            steps: self.steps,
+            pc: self.pc,
+            reason,
+        };
+        self.halted = Some(halt);
+        StepOutcome::Halted(halt)
+    }
+
+    /// Passes the turn to the next thread, if there is one
+    fn rotate(&mut self) {
+        if let Some(next) = self.threads.pop_front() {
+            let current = self.switch_to(next);
+            self.threads.push_back(current);
+            if let Some(undo) = &mut self.undo {
+                undo.switch = Some(Switch::Rotated);
+            }
+        }
+    }
+
+    // only called while there's another thread to take over
+    fn end_thread(&mut self) {
+        if let Some(next) = self.threads.pop_front() {
+            let ended = self.switch_to(next);
+            if let Some(undo) = &mut self.undo {
+                undo.switch = Some(Switch::Ended(ended));
+            }
+        }
+    }
+
+    /// Makes `thread` the running thread, returning the one that was
+    fn switch_to(&mut self, thread: Thread) -> Thread {
+        Thread {
+            id: mem::replace(&mut self.thread, thread.id),
+            pc: mem::replace(&mut self.pc, thread.pc),
+            direction: mem::replace(&mut self.direction, thread.direction),
+            stack: mem::replace(&mut self.stack, thread.stack),
+            register_a: mem::replace(&mut self.register_a, thread.register_a),
+            calls: mem::replace(&mut self.calls, thread.calls),
+        }
+    }
+
+    fn trace(
+        &mut self,
+        point: MatrixPoint,
+        instruction: Instruction,
+        arg: Option<Pixel>,
+        condition: Condition,
+        last: bool,
+    ) -> Result<()> {
+        let tracer = match &mut self.tracer {
+            Some(tracer) => tracer,
+            None => return Ok(()),
+        };
+
+        tracer.record(&Record {
+            step: self.steps,
+            thread: self.concurrent.then_some(self.thread),
+            point,
+            direction: self.direction,
+            instruction,
+            arg: arg.map(|arg| arg.value()),
+            condition: instruction.is_conditional().then_some(condition),
+            stack: &self.stack,
+            register_a: self.register_a,
+            tape_writes: &self.tape_writes,
+        })?;
+
+        if last {
+            tracer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn detect_loop(&mut self, instruction: Instruction) -> Result<()> {
+        // a step that HALTs can't be part of a loop, whatever state it ends in
+        if self.exit.is_some() {
+            return Ok(());
+        }
+        let mut cycles = match self.cycles.take() {
+            Some(cycles) => cycles,
+            None => return Ok(()),
+        };
+
+        // the machine's state doesn't capture what is left on the input, the
+        // host's state or the program's pixels, so a state seen before
+        // reading, calling the host or changing them isn't a reliable sign of
+        // a loop
+        let looping = if matches!(
+            instruction,
+            Instruction::Read | Instruction::ReadInt | Instruction::Syscall | Instruction::Put
+        ) {
+            cycles.reset();
+            false
+        } else {
+            cycles.observe(self)
+        };
+        self.cycles = Some(cycles);
+
+        if looping {
+            Err(ErrorKind::InfiniteLoop)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Undoes the most recent step, as long as history is being kept and
+    /// still reaches back that far. Input that was read and output that was
+    /// written stay as they are. Returns false if there was nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(History::pop) {
+            Some(undo) => undo,
+            None => return false,
+        };
+
+        // the thread that ran the step is brought back before undoing it
+        match undo.switch {
+            Some(Switch::Rotated) => {
+                if let Some(current) = self.threads.pop_back() {
+                    let next = self.switch_to(current);
+                    self.threads.push_front(next);
+                }
+            }
+            Some(Switch::Ended(thread)) => {
+                let next = self.switch_to(thread);
+                self.threads.push_front(next);
+            }
+            None => {}
+        }
+        if undo.spawned {
+            self.threads.pop_back();
+            self.next_thread -= 1;
+        }
+
+        for &pixel in undo.grid_writes.iter().rev() {
+            self.instructions[pixel.point] = pixel;
+            self.update_graph(pixel.point);
+        }
+        if let Some(rng) = undo.rng {
+            self.rng = rng;
+        }
+        if let Some((stack, tape)) = undo.snapshot {
+            self.stack = stack;
+            self.tape = tape;
+        }
+        for &(address, value) in undo.tape_writes.iter().rev() {
+            // the write succeeded, so writing the old value back can't fail
+            let _ = self.tape.set(address, value);
+        }
+        self.tape.truncate(undo.tape_len);
+        self.stack.truncate(undo.low_water);
+        self.stack.extend(undo.popped.iter().rev());
+        if undo.called {
+            self.calls.pop();
+        }
+        if let Some(frame) = undo.returned {
+            self.calls.push(frame);
+        }
+        self.register_a = undo.register_a;
+        self.pc = undo.pc;
+        self.direction = undo.direction;
+        self.steps -= 1;
+        self.halted = None;
+        if let Some(cycles) = &mut self.cycles {
+            cycles.reset();
+        }
+        true
+    }
+
+    /// Steps back until the program counter is at `point` again. Returns
+    /// false, having undone all the history there is, if it never was.
+    pub fn run_back_to(&mut self, point: MatrixPoint) -> bool {
+        while self.step_back() {
+            if self.pc == point {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Number of steps that [`VM::step_back`] can undo
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Id of the running thread. Threads are numbered from 0 in the order
+    /// they start.
+    pub fn thread(&self) -> usize {
+        self.thread
+    }
+
+    /// Number of threads that haven't finished yet, including the running one
+    pub fn thread_count(&self) -> usize {
+        self.threads.len() + 1
+    }
+
+    pub fn stack(&self) -> &[i64] {
+        &self.stack
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    pub fn register_a(&self) -> u16 {
+        self.register_a
+    }
+
+    /// Where each `CALL` that is waiting to `RETURN` came from, along with
+    /// the direction it was heading, innermost last
+    pub fn calls(&self) -> &[(MatrixPoint, Direction)] {
+        &self.calls
+    }
+
+    pub fn pc(&self) -> MatrixPoint {
+        self.pc
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Number of instructions executed since the program was loaded
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn instructions(&self) -> &Matrix<Pixel> {
+        &self.instructions
+    }
+
+    pub fn host(&self) -> &H {
+        &self.host
+    }
+
+    pub fn host_mut(&mut self) -> &mut H {
+        &mut self.host
+    }
+
+    /// Which hue means which instruction, as set with
+    /// [`VMBuilder::instruction_set`]
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
+    }
+
+    #[allow(clippy::unit_arg)]
+    fn execute_instruction(
+        &mut self,
+        instruction: Instruction,
+        arg: Option<Pixel>,
+        condition: Condition,
+    ) -> Result<()> {
+        if instruction.takes_arg() && arg.is_none() {
+            return Err(ErrorKind::MissingArgument);
+        }
+
+        match instruction {
+            Instruction::Road | Instruction::Start | Instruction::None => Ok(()),
+            Instruction::Push => Ok(self.push(arg.unwrap().value() as i64)),
+            Instruction::Add => self.arithmetic(ArithmeticMode::add),
+            Instruction::Sub => self.arithmetic(ArithmeticMode::sub),
+            Instruction::Mult => self.arithmetic(ArithmeticMode::mul),
+            Instruction::Div => self.arithmetic(ArithmeticMode::div),
+            Instruction::Modulo => self.arithmetic(ArithmeticMode::rem),
+            Instruction::LeftShift => {
+                let a = self.operand()?;
+                let shifted = self.arithmetic.shift_left(a)?;
+                Ok(self.push(shifted))
+            }
+            Instruction::RightShift => self.unary_infix(|a| a >> 1),
+            Instruction::Output => self.output(),
+            Instruction::OutputUntil => self.output_until(condition),
+            Instruction::PushA => Ok(self.push(self.tape.get(self.register_a as usize)?)),
+            Instruction::PopUntil => self.pop_until(condition),
+            Instruction::Save => self.write_tape(arg.unwrap().value() as i64),
+            Instruction::PopA => {
+                let value = self.pop()?;
+                self.write_tape(value)
+            }
+            Instruction::MovA => Ok(self.register_a = arg.unwrap().value()),
+            Instruction::And => self.infix(|a, b| a & b),
+            Instruction::Or => self.infix(|a, b| a | b),
+            Instruction::Xor => self.infix(|a, b| a ^ b),
+            Instruction::Not => self.unary_infix(|a| !a),
+            Instruction::Read => self.read(),
+            Instruction::ReadInt => self.read_int(),
+            Instruction::OutputInt => self.output_int(None),
+            Instruction::OutputIntSep => self.output_int(Some(arg.unwrap().value())),
+            Instruction::Spawn => self.spawn(),
+            // waiting is up to `step`, since it depends on where the thread was
+            Instruction::Join => Ok(()),
+            Instruction::Call => self.call(),
+            Instruction::Return => self.ret(),
+            Instruction::Branch => self.branch(condition),
+            Instruction::Dup => self.dup(),
+            Instruction::Swap => self.swap(),
+            Instruction::Over => self.over(),
+            Instruction::Rot => self.rot(),
+            Instruction::Drop => self.pop().map(|_| ()),
+            Instruction::Depth => Ok(self.push(self.stack.len() as i64)),
+            Instruction::WidePush => Ok(self.push(arg.unwrap().as_wide_data())),
+            Instruction::VarPush => self.var_push(arg.unwrap()),
+            Instruction::Syscall => self.syscall(),
+            Instruction::Halt => {
+                let code = if self.stack.is_empty() {
+                    0
+                } else {
+                    self.pop()?
+                };
+                self.exit = Some(code);
+                Ok(())
+            }
+            Instruction::Random => self.random(),
+            Instruction::Get => self.get(),
+            Instruction::Put => self.put(),
+            Instruction::Jump => self.jump(),
+        }
+    }
+
+    fn push(&mut self, arg: i64) {
+        self.stack.push(arg)
+    }
+
+    fn pop(&mut self) -> Result<i64> {
+        let value = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+        if let Some(undo) = &mut self.undo {
+            undo.popped(value, self.stack.len());
+        }
+        Ok(value)
+    }
+
+    // the value an instruction pops first, which a pixel can take from
+    // tape[A] instead in extended mode
+    fn operand(&mut self) -> Result<i64> {
+        if self.modifiers.tape_operand {
+            self.tape.get(self.register_a as usize)
+        } else {
+            self.pop()
+        }
+    }
+
+    // reads base-360 digits from the argument onwards, most significant
+    // first, up to a gray pixel that ends the number. A dark one (less than
+    // 50% light) makes it negative.
+    fn var_push(&mut self, first: Pixel) -> Result<()> {
+        let mut value: i64 = 0;
+        let mut digits = 0;
+        let mut pixel = first;
+        while !pixel.is_gray() {
+            digits += 1;
+            // nine digits never fit, and a loop of digits never ends
+            if digits > MAX_DIGITS {
+                return Err(ErrorKind::Overflow);
+            }
+            let digit = (pixel.value() % 360) as i64;
+            value = value
+                .checked_mul(360)
+                .and_then(|value| value.checked_add(digit))
+                .ok_or(ErrorKind::Overflow)?;
+            pixel = self.get_next_instruction();
+            self.pc = pixel.point;
+        }
+
+        self.push(if pixel.hsl.l < 50 { -value } else { value });
+        Ok(())
+    }
+
+    // the generator is saved as it was before its first number in the step,
+    // so undoing the step gives the same numbers again
+    fn random(&mut self) -> Result<()> {
+        let high = self.pop()?;
+        let low = self.pop()?;
+        if let Some(undo) = &mut self.undo {
+            undo.rng.get_or_insert(self.rng);
+        }
+        let value = self.rng.between(low, high);
+        self.push(value);
+        Ok(())
+    }
+
+    // the host can change the stack and tape in any way, so undoing the step
+    // means putting them back as they were
+    fn syscall(&mut self) -> Result<()> {
+        let number = self.pop()?;
+        if let Some(undo) = &mut self.undo {
+            undo.snapshot
+                .get_or_insert_with(|| (self.stack.clone(), self.tape.clone()));
+        }
+
+        let mut machine = Machine {
+            stack: &mut self.stack,
+            tape: &mut self.tape,
+            register_a: self.register_a,
+        };
+        self.host.syscall(number, &mut machine)
+    }
+
+    fn spawn(&mut self) -> Result<()> {
+        if !self.concurrent {
+            return Err(ErrorKind::ThreadsDisabled);
+        }
+
+        self.threads.push_back(Thread {
+            id: self.next_thread,
+            pc: self.pc,
+            direction: self.direction.opposite(),
+            stack: self.stack.clone(),
+            register_a: self.register_a,
+            calls: self.calls.clone(),
+        });
+        self.next_thread += 1;
+        if let Some(undo) = &mut self.undo {
+            undo.spawned = true;
+        }
+        Ok(())
+    }
+
+    // like START, the target itself isn't executed, so the program continues
+    // from the pixel after it
+    fn call(&mut self) -> Result<()> {
+        let y = self.pop()?;
+        let x = self.pop()?;
+        let target = self.point(x, y)?;
+        if self.calls.len() == self.call_depth {
+            return Err(ErrorKind::CallDepthExceeded {
+                limit: self.call_depth,
+            });
+        }
+
+        self.calls.push((self.pc, self.direction));
+        if let Some(undo) = &mut self.undo {
+            undo.called = true;
+        }
+        self.pc = target;
+        Ok(())
+    }
+
+    // directions are numbered clockwise from north, and wrap around
+    fn jump(&mut self) -> Result<()> {
+        let direction = self.pop()?;
+        let y = self.pop()?;
+        let x = self.pop()?;
+        self.pc = self.point(x, y)?;
+        self.direction = match direction.rem_euclid(4) {
+            0 => Direction::North,
+            1 => Direction::East,
+            2 => Direction::South,
+            _ => Direction::West,
+        };
+        Ok(())
+    }
+
+    fn ret(&mut self) -> Result<()> {
+        let (pc, direction) = self.calls.pop().ok_or(ErrorKind::ReturnStackUnderflow)?;
+        if let Some(undo) = &mut self.undo {
+            undo.returned = Some((pc, direction));
+        }
+        self.pc = pc;
+        self.direction = direction;
+        Ok(())
+    }
+
+    fn get(&mut self) -> Result<()> {
+        let y = self.pop()?;
+        let x = self.pop()?;
+        let point = self.point(x, y)?;
+        self.push(self.instructions[point].value() as i64);
+        Ok(())
+    }
+
+    // hues wrap around, so any value can be written
+    fn put(&mut self) -> Result<()> {
+        let y = self.pop()?;
+        let x = self.pop()?;
+        let hue = self.pop()?.rem_euclid(360) as u16;
+        let point = self.point(x, y)?;
+
+        let old = self.instructions[point];
+        let mut pixel = old;
+        if pixel.is_gray() {
+            // a gray pixel's color doesn't show its hue, so saving the image
+            // would lose it
+            pixel = Pixel::new(
+                Hsl {
+                    h: hue,
+                    s: 100,
+                    l: 50,
+                },
+                point,
+            );
+        } else {
+            pixel.set_hue(hue);
+        }
+        if pixel == old {
+            return Ok(());
+        }
+
+        self.instructions[point] = pixel;
+        if let Some(undo) = &mut self.undo {
+            undo.grid_writes.push(old);
+        }
+        self.update_graph(point);
+        Ok(())
+    }
+
+    /// Checks that popped coordinates are a pixel of the program
+    fn point(&self, x: i64, y: i64) -> Result<MatrixPoint> {
+        match (usize::try_from(x), usize::try_from(y)) {
+            (Ok(x), Ok(y)) if self.instructions.get(MatrixPoint(x, y)).is_some() => {
+                Ok(MatrixPoint(x, y))
+            }
+            _ => Err(ErrorKind::OutOfBounds { x, y }),
+        }
+    }
+
+    // the stack shuffles pop and push rather than touching the stack in place,
+    // so they underflow like every other instruction and can be undone
+
+    fn dup(&mut self) -> Result<()> {
+        let a = self.pop()?;
+        self.push(a);
+        self.push(a);
+        Ok(())
+    }
+
+    fn swap(&mut self) -> Result<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(b);
+        self.push(a);
+        Ok(())
+    }
+
+    fn over(&mut self) -> Result<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(a);
+        self.push(b);
+        self.push(a);
+        Ok(())
+    }
+
+    // a b c -> b c a
+    fn rot(&mut self) -> Result<()> {
+        let c = self.pop()?;
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(b);
+        self.push(c);
+        self.push(a);
+        Ok(())
+    }
+
+    // writes to tape[registerA]
+    fn write_tape(&mut self, value: i64) -> Result<()> {
+        let address = self.register_a as usize;
+        if let Some(undo) = &mut self.undo {
+            undo.tape_writes.push((address, self.tape.get(address)?));
+        }
+        self.tape.set(address, value)?;
+        if self.tracer.is_some() {
+            self.tape_writes.push((address, value));
+        }
+        Ok(())
+    }
+
+    // bitwise infix operations (and, or, xor), which can't overflow
+    fn infix(&mut self, f: fn(i64, i64) -> i64) -> Result<()> {
+        let b = self.operand()?;
+        let a = self.pop()?;
+        self.stack.push(f(a, b));
+        Ok(())
+    }
+
+    // infix operations that can overflow or divide by zero
+    // (add, sub, mult, div, modulo), following the VM's arithmetic mode
+    fn arithmetic(&mut self, f: fn(ArithmeticMode, i64, i64) -> Result<i64>) -> Result<()> {
+        let b = self.operand()?;
+        let a = self.pop()?;
+        self.stack.push(f(self.arithmetic, a, b)?);
+        Ok(())
+    }
+
+    // infix operations that use a constant (and subsequently only pops once)
+    fn unary_infix(&mut self, f: fn(i64) -> i64) -> Result<()> {
+        let a = self.operand()?;
+        self.stack.push(f(a));
+        Ok(())
+    }
+
+    fn output_until(&mut self, condition: Condition) -> Result<()> {
+        self.pop_loop(condition, true)
+    }
+
+    // the program then leaves the junction by whichever side it turned to
+    fn branch(&mut self, condition: Condition) -> Result<()> {
+        let c = self.pop()?;
+        self.direction = if condition.compare(c) {
+            self.direction.clockwise()
+        } else {
+            self.direction.counter_clockwise()
+        };
+        Ok(())
+    }
+
+    fn pop_until(&mut self, condition: Condition) -> Result<()> {
+        self.pop_loop(condition, false)
+    }
+
+    // pops until a value meets the condition, writing out the others for
+    // OUTPUT UNTIL. With loop exits, running out of values turns clockwise
+    // rather than halting, and the value that met the condition can be kept.
+    fn pop_loop(&mut self, condition: Condition, output: bool) -> Result<()> {
+        let keep = self.loop_exits && self.keeps_sentinel();
+
+        loop {
+            let c = match self.pop() {
+                Err(ErrorKind::StackUnderflow) if self.loop_exits => {
+                    self.direction = self.direction.clockwise();
+                    return Ok(());
+                }
+                c => c?,
+            };
+
+            if condition.compare(c) {
+                if keep {
+                    self.push(c);
+                }
+                return Ok(());
+            }
+            if output {
+                self.output.write(&mut self.out, c)?;
+            }
+        }
+    }
+
+    // a PUSH pixel one back and one counter-clockwise from a loop, which is
+    // the mirror image of where its conditional goes
+    fn keeps_sentinel(&self) -> bool {
+        let back = self.direction.opposite();
+        let left = self.direction.counter_clockwise();
+        matches!(
+            self.instructions.corner(self.pc, back, left),
+            Some(pixel) if self.decode(pixel) == Instruction::Push
+        )
+    }
+
+    fn output(&mut self) -> Result<()> {
+        let c = self.operand()?;
+        self.output.write(&mut self.out, c)
+    }
+
+    // outputs the top of the stack in decimal, optionally followed by
+    // the character whose code is `separator`
+    fn output_int(&mut self, separator: Option<u16>) -> Result<()> {
+        let n = self.operand()?;
+        write!(self.out, "{}", n)?;
+
+        if let Some(c) = separator {
+            self.output.write(&mut self.out, c as i64)?;
+        }
+
+        Ok(())
+    }
+
+    // reads a single byte, pushing -1 once the input is exhausted
+    fn read(&mut self) -> Result<()> {
+        let c = match self.peek_input()? {
+            Some(byte) => {
+                self.input.consume(1);
+                byte as i64
+            }
+            None => -1,
+        };
+
+        self.push(c);
+        Ok(())
+    }
+
+    // skips leading whitespace and reads an optionally signed decimal integer,
+    // stopping at (but not consuming) the first byte that isn't a digit.
+    // pushes -1 if the input is exhausted before a number starts.
+    fn read_int(&mut self) -> Result<()> {
+        while let Some(byte) = self.peek_input()? {
+            if !byte.is_ascii_whitespace() {
+                break;
+            }
+            self.input.consume(1);
+        }
+
+        let negative = match self.peek_input()? {
+            None => {
+                self.push(-1);
+                return Ok(());
+            }
+            Some(sign @ (b'-' | b'+')) => {
+                self.input.consume(1);
+                sign == b'-'
+            }
+            Some(_) => false,
+        };
+
+        let mut n: Option<i64> = None;
+        while let Some(byte) = self.peek_input()? {
+            if !byte.is_ascii_digit() {
+                break;
+            }
+            self.input.consume(1);
+            let digit = (byte - b'0') as i64;
+            n = n
+                .unwrap_or(0)
+                .checked_mul(10)
+                .and_then(|n| n.checked_add(digit));
+            if n.is_none() {
+                return Err(ErrorKind::InvalidInput("integer is out of range"));
+            }
+        }
+
+        let n = n.ok_or(ErrorKind::InvalidInput("expected an integer"))?;
+        self.push(if negative { -n } else { n });
+        Ok(())
+    }
+
+    fn peek_input(&mut self) -> Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    // prioritize roads over all other instructions besides the one in front of us
+    pub fn get_next_instruction(&mut self) -> Pixel {
+        let (direction, pixel) = self.next_pixel();
+        self.direction = direction;
+        pixel
+    }
+
+    /// The pixel the next step starts by executing, without moving there
+    pub fn peek(&self) -> Pixel {
+        let (_, point) = self
+            .decode_next(self.pc, self.direction)
+            .expect("a program needs more than one pixel");
+        self.instructions[point]
+    }
+
+    fn next_pixel(&mut self) -> (Direction, Pixel) {
+        let next = match self.graph.take() {
+            Some(mut graph) => {
+                let next = graph.next(self.pc, self.direction, |pc, direction| {
+                    self.decode_next(pc, direction)
+                });
+                self.graph = Some(graph);
+                next
+            }
+            None => self.decode_next(self.pc, self.direction),
+        };
+        let (direction, point) = next.expect("a program needs more than one pixel");
+        (direction, self.instructions[point])
+    }
+
+    // passes any roads ahead in one go, counting each of them as a step
+    fn skip_roads(&mut self) -> Pixel {
+        let skip = self.graph.take().and_then(|mut graph| {
+            let skip = graph.skip_roads(
+                self.pc,
+                self.direction,
+                |pc, direction| self.decode_next(pc, direction),
+                |point| self.decode(self.instructions[point]) == Instruction::Road,
+            );
+            self.graph = Some(graph);
+            skip
+        });
+
+        match skip {
+            Some((direction, point, roads)) => {
+                self.direction = direction;
+                self.steps += roads;
+                self.instructions[point]
+            }
+            None => self.get_next_instruction(),
+        }
+    }
+
+    fn decode(&self, pixel: Pixel) -> Instruction {
+        self.instruction_set.instruction(pixel.value())
+    }
+
+    // roads can only be skipped when nothing looks at the steps in between
+    fn can_skip_roads(&self) -> bool {
+        self.threads.is_empty()
+            && self.tracer.is_none()
+            && self.history.is_none()
+            && self.cycles.is_none()
+    }
+
+    // works out where the program counter goes next from `pc`, which is what
+    // the graph remembers
+    fn decode_next(
+        &self,
+        pc: MatrixPoint,
+        direction: Direction,
+    ) -> Option<(Direction, MatrixPoint)> {
+        let mut next_pixels = self.next_pixels(pc, direction);
+        let (first_dir, first_pixel) = next_pixels.clone().next()?;
+        let first_road = next_pixels
+            .clone()
+            .find(|(_dir, pixel)| self.decode(*pixel) == Instruction::Road);
+
+        // take the first road available, unless it's in the opposite direction
+        // only take the opposite road if there are no other options
+        if let Some((dir, road)) = first_road {
+            if dir != direction.opposite() {
+                return Some((dir, road.point));
+            }
+        }
+
+        // if there are no roads that don't lead backwards & there is an
+        // instruction in front, take it
+        if first_dir == direction {
+            Some((first_dir, first_pixel.point))
+        } else {
+            // otherwise - if there are no roads to the left or right & nothing in front -
+            // we go backwards (no matter if it's a road or not)
+            next_pixels
+                .next_back()
+                .map(|(dir, pixel)| (dir, pixel.point))
+        }
+    }
+
+    // try the pixel ahead of us. If that doesn't exist,
+    // try the pixel to the 'right' (counter-clockwise & opposite). If that doesn't exist,
+    // try the pixel to the 'left' (counter-clockwise). If that doesn't exist,
+    // go back the way we came
+    fn next_pixels(
+        &self,
+        pc: MatrixPoint,
+        direction: Direction,
+    ) -> impl DoubleEndedIterator<Item = (Direction, Pixel)> + Clone + '_ {
+        let directions = [
+            direction,                     // forward
+            direction.clockwise(),         // right
+            direction.counter_clockwise(), // left
+            direction.opposite(),          // back
+        ];
+
+        directions
+            .into_iter()
+            .filter_map(move |dir| Some((dir, self.instructions.go(pc, dir)?)))
+    }
+
+    // this is called when the program counter is _at_ the conditional (roundabout).
+    // to get the correct condition (traffic light) we need to move one pixel back
+    // to where we came from and check on the right-hand side.
+    fn get_condition(&self) -> Condition {
+        let back = self.direction.opposite();
+        let right = self.direction.clockwise();
+        if let Some(point) = self.instructions.corner(self.pc, back, right) {
+            self.instruction_set.condition(point.value())
+        } else {
+            Condition::Equal
+        }
+    }
+
+    fn find_start(&self) -> MatrixPoint {
+        // default start coordinates
+        self.find_starts()
+            .first()
+            .copied()
+            .unwrap_or(MatrixPoint(0, 0))
+    }
+
+    // every START pixel, row by row
+    fn find_starts(&self) -> Vec<MatrixPoint> {
+        let mut starts = vec![];
+        for (row_idx, row) in self.instructions.matrix.iter().enumerate() {
+            for (col_idx, pixel) in row.iter().enumerate() {
+                if self.decode(*pixel) == Instruction::Start {
+                    starts.push(MatrixPoint(col_idx, row_idx));
+                }
+            }
+        }
+        starts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Direction, OutputMode, StepOutcome, Syscalls, TapeSize, VMBuilder, VM};
+    use crate::pixel::START;
+    use crate::vm::Direction::{East, North, South, West};
+    use crate::{ErrorKind, Halt, HaltReason, Instruction, InstructionSet};
+    use crate::{Matrix, MatrixPoint, Pixel};
+    use image::Rgba;
+    use std::cell::RefCell;
+    use std::io::{self, BufReader, Write};
+    use std::rc::Rc;
+
+    fn init_vm(matrix: Vec<Vec<u16>>) -> VM<BufReader<io::Stdin>, io::Stdout> {
+        // we aren't checking the output in these tests, so it's okay
+        // to return io::Stdout
+        let mut vm = VM::default();
+        vm.instructions = Matrix::from_hues(matrix);
+        vm.pc = vm.find_start();
+        vm
+    }
+
+    #[test]
+    fn test_start_one_d() {
+        let vm = init_vm(vec![vec![
+            START, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306,
+        ]]);
+
+        assert_eq!(vm.pc, MatrixPoint(0, 0));
+    }
+
+    #[test]
+    fn test_start_two_d() {
+        let vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, START, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        assert_eq!(vm.pc, MatrixPoint(5, 1));
+    }
+
+    #[test]
+    fn test_start_bounds() {
+        let vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, 3, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, START],
+        ]);
+
+        assert_eq!(vm.pc, MatrixPoint(11, 2));
+    }
+
+    fn compare_pixels(
+        actual: impl Iterator<Item = (Direction, Pixel)>,
+        expected: Vec<(Direction, u16)>,
+    ) {
+        let actual: Vec<_> = actual.collect();
+        assert_eq!(actual.len(), expected.len());
+
+        for (idx, (dir, pixel)) in actual.iter().enumerate() {
+            let (expected_dir, expected_pixel) = expected[idx];
+            assert_eq!(*dir, expected_dir);
+            assert_eq!(pixel.value(), expected_pixel);
+        }
+    }
+
+    #[test]
+    fn test_get_next_pixels_east() {
+        let vm = init_vm(vec![vec![
+            START, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306,
+        ]]);
+
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+        let expected = vec![(East, 180)];
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_east_middle() {
+        let vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, START, 2, 108, 36, 48, 108, 306],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        assert_eq!(vm.pc, MatrixPoint(5, 1));
+        let pixels = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(East, 2), (South, 36), (North, 37), (West, 1)];
+
+        compare_pixels(pixels, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_east_bound() {
+        let vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 36, 48, 108, 310],
+            vec![0, 180, 180, 36, 1, 108, 2, 108, 36, 48, 108, START],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        assert_eq!(vm.pc, MatrixPoint(11, 1));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(South, 306), (North, 310), (West, 108)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_north() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 36, 48, 108, 310],
+            vec![0, 180, 180, 36, 1, 108, 2, 108, 36, START, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = North;
+
+        assert_eq!(vm.pc, MatrixPoint(9, 1));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(North, 48), (East, 108), (West, 36), (South, 48)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_north_bound() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, START, 48, 108, 310],
+            vec![0, 180, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = North;
+
+        assert_eq!(vm.pc, MatrixPoint(8, 0));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(East, 48), (West, 108), (South, 36)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_west() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![0, 180, START, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = West;
+
+        assert_eq!(vm.pc, MatrixPoint(2, 1));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(West, 180), (North, 180), (South, 180), (East, 36)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_south_bound() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![0, 180, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![START, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = South;
+
+        assert_eq!(vm.pc, MatrixPoint(0, 2));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(East, 180), (North, 0)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_south() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, START, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![0, 180, 100, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = South;
+
+        assert_eq!(vm.pc, MatrixPoint(2, 0));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(South, 100), (West, 180), (East, 36)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_pixels_west_bound() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![START, 180, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = West;
+
+        assert_eq!(vm.pc, MatrixPoint(0, 1));
+        let actual = vm.next_pixels(vm.pc, vm.direction);
+
+        let expected = vec![(North, 0), (South, 0), (East, 180)];
+
+        compare_pixels(actual, expected);
+    }
+
+    #[test]
+    fn test_get_next_instruction() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![START, 180, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = East;
+
+        assert_eq!(vm.pc, MatrixPoint(0, 1));
+        let pixel = vm.get_next_instruction();
+
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_get_next_instruction1() {
+        let mut vm = init_vm(vec![
+            vec![0, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![START, 1, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![0, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = East;
+
+        let pixel = vm.get_next_instruction();
+
+        assert_eq!(pixel.value(), 1);
+    }
+
+    #[test]
+    fn test_get_next_instruction2() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![START, 1, 180, 36, 1, 18, 2, 108, 36, 42, 108, 314],
+            vec![180, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = East;
+
+        let pixel = vm.get_next_instruction();
+
+        // take the road to the 'right' (south)
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_get_next_instruction3() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![180, 180, 180, 36, 1, 18, 2, 108, 36, 42, 180, START],
+            vec![180, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = East;
+
+        let pixel = vm.get_next_instruction();
+
+        // turn around
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_get_next_instruction4() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![180, 180, 180, 36, 180, START, 2, 108, 36, 42, 180, 200],
+            vec![180, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = North;
+
+        let pixel = vm.get_next_instruction();
+
+        // go 'left'
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_get_next_instruction5() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 310],
+            vec![180, 180, 180, 36, 1, START, 2, 108, 36, 42, 180, 200],
+            vec![180, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = North;
+
+        let pixel = vm.get_next_instruction();
+
+        assert_eq!(pixel.value(), 37);
+    }
+
+    #[test]
+    fn test_get_next_instruction6() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 180],
+            vec![180, 180, 180, 36, 1, 18, 2, 108, 36, 42, 180, START],
+            vec![180, 180, 180, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = East;
+
+        let pixel = vm.get_next_instruction();
+
+        // don't turn around if there's another road available
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_get_next_instruction7() {
+        let mut vm = init_vm(vec![
+            vec![180, 180, 180, 36, 1, 37, 2, 108, 70, 48, 108, 180],
+            vec![180, 180, 180, 36, 1, 18, 2, 108, 36, 42, 180, 180],
+            vec![180, 1, START, 36, 1, 36, 2, 108, 36, 48, 108, 306],
+        ]);
+
+        vm.direction = North;
+
+        let pixel = vm.get_next_instruction();
+
+        // go north
+        assert_eq!(pixel.value(), 180);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut vm = VM::new(&b"hi"[..], vec![]);
+        vm.read().unwrap();
+        vm.read().unwrap();
+        vm.read().unwrap();
+
+        assert_eq!(vm.stack, vec![104, 105, -1]);
+    }
+
+    #[test]
+    fn test_read_int() {
+        let mut vm = VM::new(&b" 42\n-7+3x"[..], vec![]);
+        vm.read_int().unwrap();
+        vm.read_int().unwrap();
+        vm.read_int().unwrap();
+
+        assert_eq!(vm.stack, vec![42, -7, 3]);
+
+        // the byte that ended the number is left for the next read
+        vm.read().unwrap();
+        assert_eq!(vm.stack.pop(), Some(120));
+
+        vm.read_int().unwrap();
+        assert_eq!(vm.stack.pop(), Some(-1));
+    }
+
+    #[test]
+    fn test_read_int_invalid() {
+        let mut vm = VM::new(&b"abc"[..], vec![]);
+        assert!(vm.read_int().is_err());
+
+        let mut vm = VM::new(&b"99999999999999999999"[..], vec![]);
+        assert!(vm.read_int().is_err());
+    }
+
+    #[test]
+    fn test_output_int() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.stack = vec![-12, 1000, 7];
+        vm.output_int(Some(32)).unwrap();
+        vm.output_int(Some(10)).unwrap();
+        vm.output_int(None).unwrap();
+
+        assert_eq!(vm.out, b"7 1000\n-12");
+        assert!(vm.output_int(None).is_err());
+    }
+
+    #[test]
+    fn test_execute_halts_on_empty_stack() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(vec![vec![START, 180, 108]]));
+
+        assert_eq!(
+            halt.unwrap(),
+            Halt {
+                steps: 2,
+                pc: MatrixPoint(2, 0),
+                reason: HaltReason::EmptyStack
+            }
+        );
+    }
+
+    #[test]
+    fn test_execute_division_by_zero() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 1, 36, 0, 162]]))
+            .unwrap_err();
+
+        assert!(matches!(err.kind, ErrorKind::DivisionByZero));
+        assert_eq!(err.pc, MatrixPoint(5, 0));
+        assert_eq!(err.direction, East);
+        assert_eq!(err.instruction, Instruction::Div);
+    }
+
+    #[test]
+    fn test_execute_tape_out_of_range() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 72, 360, 0]]))
+            .unwrap_err();
+
+        assert!(matches!(
+            err.kind,
+            ErrorKind::TapeOutOfRange {
+                address: 360,
+                size: 360
+            }
+        ));
+        assert_eq!(err.pc, MatrixPoint(3, 0));
+
+        let mut vm = VMBuilder::new()
+            .tape_size(TapeSize::Growable)
+            .build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![START, 72, 360, 36, 9, 90]]));
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.tape().get(360).unwrap(), 9);
+        assert_eq!(vm.tape().cells().len(), 361);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![START, 36, 5, 36, 7, 108, 90]]));
+        assert_eq!(vm.pc(), MatrixPoint(0, 0));
+        assert_eq!(vm.direction(), East);
+
+        assert_eq!(vm.step().unwrap(), StepOutcome::Running);
+        assert_eq!(vm.pc(), MatrixPoint(2, 0));
+        assert_eq!(vm.stack(), &[5]);
+
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.stack(), &[12]);
+        assert_eq!(vm.steps(), 3);
+
+        // POPA
+        vm.step().unwrap();
+        assert_eq!(vm.tape().get(0).unwrap(), 12);
+        assert!(vm.stack().is_empty());
+
+        // turns around at the edge and pops the empty stack with ADD
+        let outcome = vm.step().unwrap();
+        assert_eq!(vm.direction(), West);
+        let halt = match outcome {
+            StepOutcome::Halted(halt) => halt,
+            StepOutcome::Running => panic!("expected the program to halt"),
+        };
+        assert_eq!(halt.pc, MatrixPoint(5, 0));
+        assert_eq!(vm.step().unwrap(), StepOutcome::Halted(halt));
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut vm = VMBuilder::new()
+            .tape_size(TapeSize::Growable)
+            .history(3)
+            .build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![START, 36, 5, 36, 7, 108, 90]]));
+        assert!(!vm.step_back());
+
+        while vm.step().unwrap() == StepOutcome::Running {}
+        assert_eq!(vm.history_len(), 3);
+
+        // undoes the halt, which happens again when stepping forward
+        assert!(vm.step_back());
+        assert_eq!(vm.pc(), MatrixPoint(6, 0));
+        assert!(matches!(vm.step().unwrap(), StepOutcome::Halted(_)));
+        assert!(vm.step_back());
+        assert_eq!(vm.direction(), East);
+
+        // POPA
+        assert!(vm.step_back());
+        assert_eq!(vm.stack(), &[12]);
+        assert!(vm.tape().cells().is_empty());
+
+        // ADD
+        assert!(vm.step_back());
+        assert_eq!(vm.stack(), &[5, 7]);
+        assert_eq!(vm.pc(), MatrixPoint(4, 0));
+        assert_eq!(vm.steps(), 2);
+
+        // older steps have been forgotten
+        assert!(!vm.step_back());
+        assert_eq!(vm.step().unwrap(), StepOutcome::Running);
+        assert_eq!(vm.stack(), &[12]);
+    }
+
+    #[test]
+    fn test_halt() {
+        // PUSH 7, HALT
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 7, 175, 36, 1]]))
+            .unwrap();
+        assert_eq!(halt.reason, HaltReason::Instruction(7));
+        assert_eq!(halt.exit_code(), 7);
+        assert_eq!(halt.pc, MatrixPoint(3, 0));
+
+        // an empty stack gives 0, and other threads stop too
+        let mut vm = VMBuilder::new().concurrent(true).build(io::empty(), vec![]);
+        let halt = vm
+            .execute(Matrix::from_hues(vec![
+                vec![START, 175, 36, 1],
+                vec![START, 36, 1, 36],
+            ]))
+            .unwrap();
+        assert_eq!(halt.reason, HaltReason::Instruction(0));
+        assert_eq!(vm.thread_count(), 2);
+    }
+
+    #[test]
+    fn test_random() {
+        // rolls a die three times with PUSH 1, PUSH 6, RANDOM, OUTPUT INT
+        let roll = [36, 1, 36, 6, 189, 27];
+        let program =
+            || Matrix::from_hues(vec![[&[START][..], &roll, &roll, &roll, &[27]].concat()]);
+        let rolls = |seed| {
+            let mut vm = VMBuilder::new().seed(seed).build(io::empty(), vec![]);
+            vm.execute(program()).unwrap();
+            String::from_utf8(vm.out).unwrap()
+        };
+        assert_eq!(rolls(7), rolls(7));
+        assert!((0..100).any(|seed| rolls(seed) != rolls(7)));
+        for c in rolls(7).chars() {
+            assert!(('1'..='6').contains(&c));
+        }
+
+        // stepping back over RANDOM gives the same number again
+        let mut vm = VMBuilder::new().history(10).build(io::empty(), vec![]);
+        vm.load(program());
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+        let value = vm.stack.clone();
+        vm.step_back();
+        vm.step().unwrap();
+        assert_eq!(vm.stack, value);
+    }
+
+    #[test]
+    fn test_output_modes() {
+        // PUSH 0, PUSH 233, OUTPUT UNTIL, then OUTPUT finds the stack empty
+        let program = || Matrix::from_hues(vec![vec![START, 36, 0, 36, 233, 324, 306]]);
+        let expected: [(OutputMode, &[u8]); 3] = [
+            (OutputMode::Legacy, "é".as_bytes()),
+            (OutputMode::Bytes, &[233]),
+            (OutputMode::Unicode, "é".as_bytes()),
+        ];
+        for (mode, out) in expected {
+            let mut vm = VMBuilder::new().output(mode).build(io::empty(), vec![]);
+            vm.execute(program()).unwrap();
+            assert_eq!(vm.out, out, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn test_syscall() {
+        // squares the top of the stack, and writes the depth of the stack
+        // to tape[A]
+        let host = Syscalls::new()
+            .register(1, |machine| {
+                let n = machine.pop()?;
+                machine.push(n * n);
+                Ok(())
+            })
+            .register(2, |machine| {
+                let depth = machine.stack().len() as i64;
+                let address = machine.register_a() as usize;
+                machine.tape().set(address, depth)
+            });
+        let mut vm = VMBuilder::new()
+            .host(host)
+            .history(10)
+            .build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![
+            START, 36, 7, 36, 1, 155, 36, 2, 155, 27, 27,
+        ]]));
+        while vm.step().unwrap() == StepOutcome::Running {}
+        assert_eq!(vm.out, b"49");
+        assert_eq!(vm.tape().get(0).unwrap(), 1);
+
+        // undoing a SYSCALL puts back whatever the host changed
+        for _ in 0..3 {
+            assert!(vm.step_back());
+        }
+        assert_eq!(vm.stack(), &[49, 2]);
+        assert_eq!(vm.tape().get(0).unwrap(), 0);
+        for _ in 0..2 {
+            assert!(vm.step_back());
+        }
+        assert_eq!(vm.stack(), &[7, 1]);
+    }
+
+    #[test]
+    fn test_unknown_syscall() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 3, 155]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::UnknownSyscall(3)));
+
+        let host = Syscalls::new().register(3, |_| Err(ErrorKind::Host("no".to_string())));
+        let mut vm = VMBuilder::new().host(host).build(io::empty(), vec![]);
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 3, 155]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Host(_)));
+    }
+
+    #[test]
+    fn test_run_back_to() {
+        let mut vm = VMBuilder::new().history(100).build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![START, 36, 5, 36, 7, 108, 90]]));
+        while vm.step().unwrap() == StepOutcome::Running {}
+
+        assert!(vm.run_back_to(MatrixPoint(2, 0)));
+        assert_eq!(vm.stack(), &[5]);
+        assert_eq!(vm.steps(), 1);
+
+        assert!(!vm.run_back_to(MatrixPoint(6, 0)));
+        assert_eq!(vm.pc(), MatrixPoint(0, 0));
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    fn test_threads() {
+        // 13 is just data
+        let program = vec![
+            vec![START, 36, 1, 27, 108],
+            vec![13, 13, 13, 13, 13],
+            vec![START, 36, 2, 27, 108],
+        ];
+
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(program.clone())).unwrap();
+        assert_eq!(halt.steps, 3);
+        assert_eq!(vm.out, b"1");
+
+        let mut vm = VMBuilder::new().concurrent(true).build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(program));
+        assert_eq!(vm.thread_count(), 2);
+        vm.step().unwrap();
+        assert_eq!(vm.thread(), 1);
+        assert_eq!(vm.pc(), MatrixPoint(0, 2));
+
+        while vm.step().unwrap() == StepOutcome::Running {}
+        assert_eq!(vm.out, b"12");
+        assert_eq!(vm.steps(), 6);
+        assert_eq!(vm.pc(), MatrixPoint(4, 2));
+    }
+
+    #[test]
+    fn test_spawn_and_join() {
+        // PUSH 4 and SPAWN. The new thread takes the road north, where it
+        // prints the 4 it was given, then finishes on ADD. Meanwhile the
+        // first thread waits on JOIN before printing its own 4.
+        let program = vec![
+            vec![13, 13, 13, 108, 13, 13, 13, 13],
+            vec![13, 13, 13, 27, 13, 13, 13, 13],
+            vec![13, 13, 13, 180, 13, 13, 13, 13],
+            vec![START, 36, 4, 45, 180, 50, 27, 108],
+        ];
+        let mut vm = VMBuilder::new()
+            .concurrent(true)
+            .history(100)
+            .build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(program.clone()));
+
+        while vm.step().unwrap() == StepOutcome::Running {}
+        assert_eq!(vm.out, b"44");
+        assert_eq!(vm.steps(), 10);
+
+        while vm.step_back() {}
+        assert_eq!(vm.pc(), MatrixPoint(0, 3));
+        assert_eq!(vm.thread_count(), 1);
+        assert!(vm.stack().is_empty());
+
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.thread(), 1);
+        assert_eq!(vm.direction(), West);
+        assert_eq!(vm.stack(), &[4]);
+
+        let mut vm = VM::new(io::empty(), vec![]);
+        let err = vm.execute(Matrix::from_hues(program)).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::ThreadsDisabled));
+    }
+
+    #[test]
+    fn test_call() {
+        // PUSH 5, then CALL (0, 2), which prints it with OUTPUT INT and
+        // RETURNs. Back in the first row, PUSH 7 and print that too.
+        let program = vec![
+            vec![START, 36, 5, 36, 0, 36, 2, 63, 36, 7, 27, 108],
+            vec![13; 12],
+            vec![13, 27, 68, 13, 13, 13, 13, 13, 13, 13, 13, 13],
+        ];
+        let mut vm = VMBuilder::new().history(10).build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(program.clone()));
+
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.pc(), MatrixPoint(0, 2));
+        assert_eq!(vm.direction(), East);
+        assert_eq!(vm.calls(), &[(MatrixPoint(7, 0), East)]);
+
+        vm.step().unwrap();
+        vm.step().unwrap();
+        assert_eq!(vm.pc(), MatrixPoint(7, 0));
+        assert!(vm.calls().is_empty());
+
+        assert!(vm.step_back());
+        assert_eq!(vm.calls(), &[(MatrixPoint(7, 0), East)]);
+        assert!(vm.run_back_to(MatrixPoint(6, 0)));
+        assert!(vm.calls().is_empty());
+
+        // stepping back doesn't take back output, so 5 is printed twice
+        while vm.step().unwrap() == StepOutcome::Running {}
+        assert_eq!(vm.out, b"557");
+        assert_eq!(vm.steps(), 9);
+
+        let mut vm = VMBuilder::new().call_depth(0).build(io::empty(), vec![]);
+        let err = vm.execute(Matrix::from_hues(program)).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::CallDepthExceeded { limit: 0 }
+        ));
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let mut vm = VM::new(io::empty(), vec![]);
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 68]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::ReturnStackUnderflow));
+
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 0, 36, 99, 63]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::OutOfBounds { x: 0, y: 99 }));
+        assert_eq!(err.pc, MatrixPoint(5, 0));
+    }
+
+    #[test]
+    fn test_get_put() {
+        // GET (1, 0), which is the PUSH, and OUTPUT INT
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.execute(Matrix::from_hues(vec![vec![
+            START, 36, 1, 36, 0, 207, 27, 27,
+        ]]))
+        .unwrap();
+        assert_eq!(vm.out, b"36");
+
+        // PUSH 5, then PUT 27 at (10, 0), turning the data there into an
+        // OUTPUT INT that runs before DROP
+        let program = || {
+            Matrix::from_hues(vec![vec![
+                START, 36, 5, 36, 27, 36, 10, 36, 0, 212, 13, 120, 27,
+            ]])
+        };
+        let mut vm = VMBuilder::new().history(10).build(io::empty(), vec![]);
+        vm.execute(program()).unwrap();
+        assert_eq!(vm.out, b"5");
+        assert_eq!(vm.instructions()[MatrixPoint(10, 0)].value(), 27);
+
+        vm.load(program());
+        for _ in 0..5 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.instructions()[MatrixPoint(10, 0)].value(), 27);
+        vm.step_back();
+        assert_eq!(vm.instructions()[MatrixPoint(10, 0)].value(), 13);
+        assert_eq!(vm.peek().point, MatrixPoint(9, 0));
+
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 20, 36, 0, 207]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::OutOfBounds { x: 20, y: 0 }));
+    }
+
+    #[test]
+    fn test_jump() {
+        // JUMP to (1, 2) heading east, skipping PUSH 9 to PUSH 4 and OUTPUT
+        // INT in the last row
+        let program = vec![
+            vec![START, 36, 1, 36, 2, 36, 1, 225, 36, 9, 27, 27],
+            vec![13; 12],
+            vec![13, 13, 36, 4, 27, 27, 13, 13, 13, 13, 13, 13],
+        ];
+        let mut vm = VMBuilder::new().history(10).build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(program.clone()));
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.pc(), MatrixPoint(1, 2));
+        assert_eq!(vm.direction(), East);
+        assert!(vm.step_back());
+        assert_eq!(vm.pc(), MatrixPoint(6, 0));
+
+        vm.execute(Matrix::from_hues(program)).unwrap();
+        assert_eq!(vm.out, b"4");
+
+        let err = vm
+            .execute(Matrix::from_hues(vec![vec![
+                START, 36, 0, 36, 99, 36, 0, 225,
+            ]]))
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::OutOfBounds { x: 0, y: 99 }));
+        assert_eq!(err.pc, MatrixPoint(7, 0));
+    }
+
+    #[test]
+    fn test_branch() {
+        // PUSH value, then BRANCH at (3, 4). Turning clockwise leads south to
+        // PUSH 1, and counter-clockwise leads north to PUSH 2, each followed
+        // by OUTPUT INT.
+        let program = |value: u16, condition: u16| {
+            let mut program = vec![vec![13; 4]; 9];
+            for (y, hue) in [108, 27, 2, 36, 81, 36, 1, 27, 108].into_iter().enumerate() {
+                program[y][3] = hue;
+            }
+            program[4][..3].copy_from_slice(&[START, 36, value]);
+            // the corner, one pixel back and one clockwise
+            program[5][2] = condition;
+            Matrix::from_hues(program)
+        };
+
+        for (value, condition, expected) in
+            [(0, 13, b"1"), (5, 13, b"2"), (5, 216, b"1"), (0, 216, b"2")]
+        {
+            let mut vm = VM::new(io::empty(), vec![]);
+            vm.execute(program(value, condition)).unwrap();
+            assert_eq!(&vm.out, expected, "{} with condition {}", value, condition);
+        }
+    }
+
+    #[test]
+    fn test_loop_exits() {
+        // PUSH 3, PUSH 0, PUSH 5, POP UNTIL, OUTPUT INT. The pixel above the
+        // last argument is the loop's back-left corner.
+        let program = |marker: u16| {
+            let mut row = vec![13; 10];
+            row[6] = marker;
+            Matrix::from_hues(vec![row, vec![START, 36, 3, 36, 0, 36, 5, 18, 27, 108]])
+        };
+
+        for (loop_exits, marker, expected) in
+            [(false, 36, b"3"), (true, 13, b"3"), (true, 36, b"0")]
+        {
+            let mut vm = VMBuilder::new()
+                .loop_exits(loop_exits)
+                .build(io::empty(), vec![]);
+            vm.execute(program(marker)).unwrap();
+            assert_eq!(&vm.out, expected);
+        }
+
+        // PUSH 5 and POP UNTIL run out of values, then turn clockwise to
+        // PUSH 7 and OUTPUT INT
+        let program = vec![
+            vec![START, 36, 5, 18],
+            vec![13, 13, 13, 36],
+            vec![13, 13, 13, 7],
+            vec![13, 13, 13, 27],
+            vec![13, 13, 13, 108],
+        ];
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(program.clone())).unwrap();
+        assert_eq!(halt.pc, MatrixPoint(3, 0));
+
+        let mut vm = VMBuilder::new().loop_exits(true).build(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(program)).unwrap();
+        assert_eq!(vm.out, b"7");
+        assert_eq!(halt.pc, MatrixPoint(3, 4));
+    }
+
+    #[test]
+    fn test_graph_matches_interpreter() {
+        for (file, pixel_size) in [
+            ("examples/hello_world.png", 1),
+            ("examples/hello_world_elaborate.png", 1),
+            ("examples/hello_world_x5.png", 5),
+            ("examples/fib_6.png", 1),
+        ] {
+            let program = || crate::load(file, pixel_size).unwrap();
+
+            let mut vm = VM::new(io::empty(), vec![]);
+            let halt = vm.execute(program()).unwrap();
+
+            // finds every next pixel as it goes, and passes roads one by one
+            let mut interpreter = VMBuilder::new().history(1).build(io::empty(), vec![]);
+            interpreter.load(program());
+            interpreter.graph = None;
+            let expected = loop {
+                if let StepOutcome::Halted(halt) = interpreter.step().unwrap() {
+                    break halt;
+                }
+            };
+
+            assert_eq!(halt, expected, "{}", file);
+            assert_eq!(vm.out, interpreter.out, "{}", file);
+        }
+    }
+
+    #[test]
+    fn test_extended_repeat() {
+        // a 70% light PUSH and a 60% light OUTPUT
+        let program = || {
+            Matrix::from_hsl(vec![vec![
+                (START, 100, 50),
+                (36, 100, 70),
+                (65, 100, 50),
+                (306, 100, 60),
+                (306, 100, 50),
+            ]])
+        };
+
+        let mut vm = VMBuilder::new().extended(true).build(io::empty(), vec![]);
+        vm.execute(program()).unwrap();
+        assert_eq!(vm.out, b"AAA");
+
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.execute(program()).unwrap();
+        assert_eq!(vm.out, b"A");
+    }
+
+    #[test]
+    fn test_extended_tape_operand() {
+        // a grayish ADD and OUTPUT INT read tape[0] instead of popping
+        let mut vm = VMBuilder::new().extended(true).build(io::empty(), vec![]);
+        let halt = vm
+            .execute(Matrix::from_hsl(vec![vec![
+                (START, 100, 50),
+                (54, 100, 50),
+                (5, 100, 50),
+                (36, 100, 50),
+                (2, 100, 50),
+                (108, 20, 50),
+                (27, 20, 50),
+                (27, 100, 50),
+            ]]))
+            .unwrap();
+        // heading back, the grayish OUTPUT INT prints tape[0] again and the
+        // ADD finds the stack empty
+        assert_eq!(vm.out, b"575");
+        assert_eq!(halt.pc, MatrixPoint(5, 0));
+        assert_eq!(vm.tape().get(0).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_wide_push() {
+        let mut program = Matrix::from_hsl(vec![vec![
+            (START, 100, 50),
+            (136, 100, 50),
+            (0, 0, 0),
+            (136, 100, 50),
+            (0, 0, 0),
+            (27, 100, 50),
+            (27, 100, 50),
+            (27, 100, 50),
+        ]]);
+        program[MatrixPoint(2, 0)] =
+            Pixel::from_rgb(Rgba([0x0f, 0x42, 0x40, 0xff]), MatrixPoint(2, 0));
+        program[MatrixPoint(4, 0)] =
+            Pixel::from_rgb(Rgba([0xff, 0xff, 0xff, 0xff]), MatrixPoint(4, 0));
+
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.execute(program).unwrap();
+        assert_eq!(vm.out, b"-11000000");
+    }
+
+    #[test]
+    fn test_var_push() {
+        // 7 * 360^2 + 257 * 360 + 280, ended by white, then -5, ended by black
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.execute(Matrix::from_hsl(vec![vec![
+            (START, 100, 50),
+            (141, 100, 50),
+            (7, 100, 50),
+            (257, 100, 50),
+            (280, 100, 50),
+            (0, 0, 100),
+            (141, 100, 50),
+            (5, 100, 50),
+            (0, 0, 0),
+            (27, 100, 50),
+            (27, 100, 50),
+            (27, 100, 50),
+        ]]))
+        .unwrap();
+        assert_eq!(vm.out, b"-51000000");
+
+        let mut digits = vec![(START, 100, 50), (141, 100, 50)];
+        digits.extend([(0, 100, 50); 9]);
+        digits.push((0, 0, 100));
+        let mut vm = VM::new(io::empty(), vec![]);
+        let error = vm.execute(Matrix::from_hsl(vec![digits])).unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::Overflow));
+    }
+
+    #[test]
+    fn test_instruction_set() {
+        let set = InstructionSet::from_toml(
+            r#"
+            [[instruction]]
+            name = "start"
+            hues = [0, 0]
+
+            [[instruction]]
+            name = "push"
+            hues = [300, 310]
+
+            [[instruction]]
+            name = "output"
+            hues = [36, 44]
+            "#,
+        )
+        .unwrap();
+        let mut vm = VMBuilder::new()
+            .instruction_set(set)
+            .build(io::empty(), vec![]);
+        // the standard START, PUSH and OUTPUT hues are data in this set
+        let halt = vm
+            .execute(Matrix::from_hues(vec![vec![300, 0, 305, 65, 40, 40]]))
+            .unwrap();
+        assert_eq!(halt.reason, HaltReason::EmptyStack);
+        assert_eq!(vm.out, b"A");
+    }
+
+    #[test]
+    fn test_road_circle() {
+        // the road from START leads into a circle that never ends
+        let mut vm = VM::new(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![
+            vec![13, 180, 180, 180],
+            vec![START, 180, 13, 180],
+            vec![13, 180, 180, 180],
+        ]));
+
+        // with nothing to skip to, `execute` would only pass the first road
+        assert_eq!(vm.skip_roads().point, MatrixPoint(1, 1));
+        assert_eq!(vm.direction(), East);
+        assert_eq!(vm.steps(), 0);
+
+        vm.pc = MatrixPoint(0, 1);
+        assert_eq!(vm.step().unwrap(), StepOutcome::Running);
+        assert_eq!(vm.steps(), 1);
+        assert_eq!(vm.pc(), MatrixPoint(1, 1));
+    }
+
+    #[test]
+    fn test_step_roads() {
+        // roads, PUSH 5, more roads, POPA and ADD on the empty stack
+        let program = vec![START, 180, 180, 36, 5, 180, 180, 90, 108];
+        let run = |builder: VMBuilder| {
+            let mut vm = builder.build(io::empty(), vec![]);
+            vm.load(Matrix::from_hues(vec![program.clone()]));
+            (0..6)
+                .map(|_| {
+                    vm.step().unwrap();
+                    (vm.pc(), vm.steps())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let plain = run(VMBuilder::new());
+        assert_eq!(plain[0], (MatrixPoint(1, 0), 1));
+        assert_eq!(plain[2], (MatrixPoint(4, 0), 3));
+        assert_eq!(plain[5], (MatrixPoint(7, 0), 6));
+        assert_eq!(run(VMBuilder::new().trace(io::sink())), plain);
+        assert_eq!(run(VMBuilder::new().history(10)), plain);
+
+        // `execute` passes the roads in one go, but counts the same steps
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(vec![program])).unwrap();
+        assert_eq!(vm.tape().get(0).unwrap(), 5);
+        assert_eq!(halt.pc, MatrixPoint(8, 0));
+        assert_eq!(halt.steps, 7);
+    }
+
+    #[test]
+    fn test_stack_shuffles() {
+        // PUSH 1, PUSH 2, PUSH 3, ROT, SWAP, OVER, DUP, DROP, DEPTH
+        let mut vm = VMBuilder::new().history(10).build(io::empty(), vec![]);
+        vm.load(Matrix::from_hues(vec![vec![
+            START, 36, 1, 36, 2, 36, 3, 117, 102, 105, 99, 120, 123,
+        ]]));
+        for _ in 0..3 {
+            vm.step().unwrap();
+        }
+
+        let expected: [&[i64]; 6] = [
+            &[2, 3, 1],
+            &[2, 1, 3],
+            &[2, 1, 3, 1],
+            &[2, 1, 3, 1, 1],
+            &[2, 1, 3, 1],
+            &[2, 1, 3, 1, 4],
+        ];
+        for stack in expected {
+            vm.step().unwrap();
+            assert_eq!(vm.stack(), stack);
+        }
+
+        assert!(vm.run_back_to(MatrixPoint(6, 0)));
+        assert_eq!(vm.stack(), &[1, 2, 3]);
+
+        // SWAP with one value on the stack
+        let mut vm = VM::new(io::empty(), vec![]);
+        let halt = vm
+            .execute(Matrix::from_hues(vec![vec![START, 36, 1, 102]]))
+            .unwrap();
+        assert_eq!(halt.pc, MatrixPoint(3, 0));
+    }
+
+    #[test]
+    fn test_detect_loops() {
+        let program = vec![
+            vec![START, 180, 180],
+            vec![180, 0, 180],
+            vec![180, 180, 180],
+        ];
+        let mut vm = VMBuilder::new()
+            .detect_loops(true)
+            .build(io::empty(), vec![]);
+        let err = vm.execute(Matrix::from_hues(program)).unwrap_err();
+
+        // the roads lead around in a circle through START, where the machine
+        // is found back in the state it was in one lap of 8 steps before
+        assert!(matches!(err.kind, ErrorKind::InfiniteLoop));
+        assert_eq!(err.pc, MatrixPoint(0, 0));
+        assert_eq!(err.direction, North);
+        assert_eq!(err.instruction, Instruction::Start);
+        assert_eq!(vm.steps(), 16);
+        assert!(vm.stack().is_empty());
+
+        // programs that halt are left alone
+        let halt = vm
+            .execute(Matrix::from_hues(vec![vec![START, 180, 108]]))
+            .unwrap();
+        assert_eq!(halt.reason, HaltReason::EmptyStack);
+        assert_eq!(halt.pc, MatrixPoint(2, 0));
+        assert_eq!(halt.steps, 2);
+    }
+
+    #[test]
+    fn test_detect_loops_halt() {
+        // after 6 data pixels, PUSH 5 and PUSH 171 leave the machine on a
+        // HALT-colored argument at step 8, which is the state loop detection
+        // remembers. JUMPing back onto the second PUSH then runs that pixel
+        // as HALT 0, ending up in the very same state.
+        let mut program = vec![START];
+        program.extend([13; 6]);
+        program.extend([36, 5, 36, 171, 36, 0, 36, 9, 36, 0, 36, 1, 225]);
+        let mut vm = VMBuilder::new()
+            .detect_loops(true)
+            .build(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(vec![program])).unwrap();
+        assert_eq!(halt.reason, HaltReason::Instruction(0));
+        assert_eq!(halt.pc, MatrixPoint(10, 0));
+        assert_eq!(halt.steps, 14);
+    }
+
+    #[test]
+    fn test_detect_loops_syscall() {
+        // a road loop of PUSH 1 & SYSCALL, whose host halts the program on
+        // its tenth call. The machine is in the same state every time around.
+        let mut calls = 0;
+        let host = Syscalls::new().register(1, move |_| {
+            calls += 1;
+            if calls == 10 {
+                Err(ErrorKind::StackUnderflow)
+            } else {
+                Ok(())
+            }
+        });
+        let program = vec![
+            vec![180, 180, 36, 1, 155, 180, 180],
+            vec![180, 13, 13, 13, 13, 13, 180],
+            vec![180, 180, 180, 180, 180, 180, 180],
+            vec![START, 13, 13, 13, 13, 13, 13],
+        ];
+        let mut vm = VMBuilder::new()
+            .detect_loops(true)
+            .host(host)
+            .build(io::empty(), vec![]);
+        let halt = vm.execute(Matrix::from_hues(program)).unwrap();
+        assert_eq!(halt.reason, HaltReason::EmptyStack);
+        assert_eq!(halt.pc, MatrixPoint(4, 0));
+    }
+
+    // a trace sink that can still be read after the VM has taken it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let buffer = SharedBuffer::default();
+        let mut vm = VMBuilder::new()
+            .trace(buffer.clone())
+            .build(io::empty(), vec![]);
+        vm.execute(Matrix::from_hues(vec![vec![START, 36, 5, 90, 18]]))
+            .unwrap();
+
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let records: Vec<serde_json::Value> = trace
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0],
+            serde_json::json!({
+                "step": 1,
+                "point": [1, 0],
+                "direction": "East",
+                "instruction": "Push",
+                "arg": 5,
+                "condition": null,
+                "stack": [5],
+                "register_a": 0,
+                "tape_writes": [],
+            })
+        );
+        assert_eq!(records[1]["tape_writes"], serde_json::json!([[0, 5]]));
+        assert_eq!(records[2]["instruction"], "PopUntil");
+        assert_eq!(records[2]["condition"], "Equal");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{Hsl, Instruction};
+
+/// How a pixel's saturation and lightness change what its instruction does,
+/// which only happens when the VM is built with
+/// [`VMBuilder::extended`](super::VMBuilder::extended)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Modifiers {
+    /// How many times the instruction runs in a row
+    pub repeat: u8,
+    /// Whether the value the instruction would pop first is read from
+    /// `tape[A]` instead
+    pub tape_operand: bool,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Modifiers {
+            repeat: 1,
+            tape_operand: false,
+        }
+    }
+}
+
+impl Modifiers {
+    /// Pixels 60% light or lighter repeat their instruction once more for
+    /// every 10% above 50%, and pixels less than 50% saturated read their
+    /// operand from the tape
+    pub fn new(hsl: Hsl, instruction: Instruction) -> Self {
+        let repeat = if hsl.l >= 60 && repeats(instruction) {
+            (hsl.l - 50) / 10 + 1
+        } else {
+            1
+        };
+        Modifiers {
+            repeat,
+            tape_operand: hsl.s < 50 && takes_operand(instruction),
+        }
+    }
+}
+
+// instructions that move the program counter (or start threads, or stop
+// the program) only run once, since running them again would happen
+// somewhere else, and loops already repeat themselves. VAR PUSH would read
+// the pixels after its number as another one.
+fn repeats(instruction: Instruction) -> bool {
+    !matches!(
+        instruction,
+        Instruction::VarPush
+            | Instruction::Halt
+            | Instruction::PopUntil
+            | Instruction::OutputUntil
+            | Instruction::Road
+            | Instruction::Start
+            | Instruction::Spawn
+            | Instruction::Join
+            | Instruction::Call
+            | Instruction::Return
+            | Instruction::Branch
+            | Instruction::Jump
+            | Instruction::None
+    )
+}
+
+// arithmetic and output, whose first popped value can come from the tape
+fn takes_operand(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Add
+            | Instruction::Sub
+            | Instruction::Mult
+            | Instruction::Div
+            | Instruction::Modulo
+            | Instruction::And
+            | Instruction::Or
+            | Instruction::Xor
+            | Instruction::Not
+            | Instruction::LeftShift
+            | Instruction::RightShift
+            | Instruction::Output
+            | Instruction::OutputInt
+            | Instruction::OutputIntSep
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::Modifiers;
+    use crate::{Hsl, Instruction};
+
+    fn repeat(l: u8) -> u8 {
+        Modifiers::new(Hsl { h: 36, s: 100, l }, Instruction::Push).repeat
+    }
+
+    #[test]
+    fn test_repeat() {
+        assert_eq!(repeat(50), 1);
+        assert_eq!(repeat(59), 1);
+        assert_eq!(repeat(60), 2);
+        assert_eq!(repeat(69), 2);
+        assert_eq!(repeat(70), 3);
+        assert_eq!(repeat(100), 6);
+        let road = Modifiers::new(
+            Hsl {
+                h: 180,
+                s: 100,
+                l: 100,
+            },
+            Instruction::Road,
+        );
+        assert_eq!(road.repeat, 1);
+    }
+}
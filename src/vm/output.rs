@@ -0,0 +1,97 @@
+use crate::ErrorKind;
+
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+
+type Result<T> = std::result::Result<T, ErrorKind>;
+
+/// How `OUTPUT`, `OUTPUT UNTIL` and the separator of `OUTPUT INT` write a
+/// value as a character
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputMode {
+    /// The low 8 bits as a Latin-1 character, so 128 to 255 are written as
+    /// two bytes of UTF-8
+    #[default]
+    Legacy,
+    /// The low 8 bits as a single byte
+    Bytes,
+    /// The value as a Unicode code point, stopping with
+    /// [`ErrorKind::InvalidCodePoint`] if it isn't one
+    Unicode,
+}
+
+impl OutputMode {
+    pub fn write<W: Write>(self, out: &mut W, value: i64) -> Result<()> {
+        match self {
+            OutputMode::Legacy => write!(out, "{}", value as u8 as char)?,
+            OutputMode::Bytes => out.write_all(&[value as u8])?,
+            OutputMode::Unicode => {
+                let c = u32::try_from(value)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(ErrorKind::InvalidCodePoint(value))?;
+                write!(out, "{}", c)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(OutputMode::Legacy),
+            "bytes" => Ok(OutputMode::Bytes),
+            "unicode" => Ok(OutputMode::Unicode),
+            _ => Err(format!(
+                "unknown output mode `{}`, expected legacy, bytes or unicode",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for OutputMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputMode::Legacy => write!(f, "legacy"),
+            OutputMode::Bytes => write!(f, "bytes"),
+            OutputMode::Unicode => write!(f, "unicode"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OutputMode::{self, Bytes, Legacy, Unicode};
+    use crate::ErrorKind;
+
+    fn written(mode: OutputMode, value: i64) -> Vec<u8> {
+        let mut out = vec![];
+        mode.write(&mut out, value).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(written(Legacy, 65), b"A");
+        assert_eq!(written(Legacy, 233), "é".as_bytes());
+        assert_eq!(written(Legacy, 256 + 65), b"A");
+
+        assert_eq!(written(Bytes, 233), [233]);
+        assert_eq!(written(Bytes, -1), [0xff]);
+
+        assert_eq!(written(Unicode, 233), "é".as_bytes());
+        assert_eq!(written(Unicode, 0x1f600), "😀".as_bytes());
+        for invalid in [-1, 0xd800, 0x110000] {
+            assert!(matches!(
+                Unicode.write(&mut vec![], invalid),
+                Err(ErrorKind::InvalidCodePoint(_))
+            ));
+        }
+        assert_eq!("bytes".parse(), Ok(OutputMode::Bytes));
+    }
+}
@@ -0,0 +1,63 @@
+/// The seed used unless one is given with [`VMBuilder::seed`](super::VMBuilder::seed)
+pub const DEFAULT_SEED: u64 = 0;
+
+/// SplitMix64, which is small, fast and good enough for programs, though not
+/// for cryptography. The same seed always gives the same numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `low` to `high`, both included, in either order
+    pub fn between(&mut self, low: i64, high: i64) -> i64 {
+        let (low, high) = (low.min(high), low.max(high));
+        // the number of values in the range, where 0 stands for all 2^64
+        let span = (high.wrapping_sub(low) as u64).wrapping_add(1);
+        let offset = match span {
+            0 => self.next_u64(),
+            span => self.next_u64() % span,
+        };
+        low.wrapping_add(offset as i64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Random;
+
+    #[test]
+    fn test_between() {
+        let mut random = Random::new(42);
+        for _ in 0..1000 {
+            let n = random.between(-3, 3);
+            assert!((-3..=3).contains(&n));
+        }
+        assert_eq!(random.between(5, 5), 5);
+        assert!((1..=6).contains(&random.between(6, 1)));
+        // the whole range doesn't overflow
+        random.between(i64::MIN, i64::MAX);
+    }
+
+    #[test]
+    fn test_seed() {
+        let numbers = |seed| {
+            let mut random = Random::new(seed);
+            (0..10).map(|_| random.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(1), numbers(1));
+        assert_ne!(numbers(1), numbers(2));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::ErrorKind;
+
+use std::fmt::{self, Display, Formatter};
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// How many cells the tape has
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TapeSize {
+    /// Exactly this many cells. Any other address is a runtime error.
+    Fixed(usize),
+    /// As many cells as the program writes to. Reading a cell that was never
+    /// written gives 0.
+    Growable,
+}
+
+impl Default for TapeSize {
+    fn default() -> Self {
+        TapeSize::Fixed(360)
+    }
+}
+
+impl FromStr for TapeSize {
+    type Err = ParseIntError;
+
+    /// Parses either a number of cells or `grow`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grow" => Ok(TapeSize::Growable),
+            _ => s.parse().map(TapeSize::Fixed),
+        }
+    }
+}
+
+impl Display for TapeSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TapeSize::Fixed(size) => write!(f, "{}", size),
+            TapeSize::Growable => write!(f, "grow"),
+        }
+    }
+}
+
+/// The VM's one-dimensional memory, addressed through register A
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tape {
+    cells: Vec<i64>,
+    size: TapeSize,
+}
+
+impl Tape {
+    pub fn new(size: TapeSize) -> Self {
+        let cells = match size {
+            TapeSize::Fixed(size) => vec![0; size],
+            TapeSize::Growable => vec![],
+        };
+        Tape { cells, size }
+    }
+
+    pub fn get(&self, address: usize) -> Result<i64, ErrorKind> {
+        match (self.cells.get(address), self.size) {
+            (Some(&value), _) => Ok(value),
+            (None, TapeSize::Growable) => Ok(0),
+            (None, TapeSize::Fixed(_)) => Err(self.out_of_range(address)),
+        }
+    }
+
+    pub fn set(&mut self, address: usize, value: i64) -> Result<(), ErrorKind> {
+        if address >= self.cells.len() {
+            match self.size {
+                TapeSize::Fixed(_) => return Err(self.out_of_range(address)),
+                // unwritten cells already read as 0
+                TapeSize::Growable if value == 0 => return Ok(()),
+                TapeSize::Growable => self.cells.resize(address + 1, 0),
+            }
+        }
+
+        self.cells[address] = value;
+        Ok(())
+    }
+
+    /// Every cell that exists. For a growable tape, this stops at the
+    /// highest address written to so far.
+    pub fn cells(&self) -> &[i64] {
+        &self.cells
+    }
+
+    /// Forgets cells a growable tape grew past `len`, when undoing writes
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.cells.truncate(len);
+    }
+
+    pub fn size(&self) -> TapeSize {
+        self.size
+    }
+
+    fn out_of_range(&self, address: usize) -> ErrorKind {
+        ErrorKind::TapeOutOfRange {
+            address,
+            size: self.cells.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Tape, TapeSize};
+    use crate::ErrorKind;
+
+    #[test]
+    fn test_fixed() {
+        let mut tape = Tape::new(TapeSize::Fixed(2));
+        tape.set(1, 7).unwrap();
+
+        assert_eq!(tape.get(1).unwrap(), 7);
+        assert!(matches!(
+            tape.set(2, 1),
+            Err(ErrorKind::TapeOutOfRange {
+                address: 2,
+                size: 2
+            })
+        ));
+        assert!(tape.get(2).is_err());
+    }
+
+    #[test]
+    fn test_growable() {
+        let mut tape = Tape::new(TapeSize::Growable);
+        assert_eq!(tape.get(1000).unwrap(), 0);
+
+        tape.set(500, 0).unwrap();
+        assert!(tape.cells().is_empty());
+
+        tape.set(3, -4).unwrap();
+        assert_eq!(tape.cells(), &[0, 0, 0, -4]);
+        assert_eq!(tape.get(3).unwrap(), -4);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("grow".parse(), Ok(TapeSize::Growable));
+        assert_eq!("16".parse(), Ok(TapeSize::Fixed(16)));
+        assert!("big".parse::<TapeSize>().is_err());
+    }
+}
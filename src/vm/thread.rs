@@ -0,0 +1,37 @@
+use super::Direction;
+use crate::MatrixPoint;
+
+/// A program counter along with the state that belongs to it alone. The
+/// thread that is running lives in the VM's own fields, and only the others
+/// are kept as `Thread`s, waiting for their turn.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Thread {
+    pub id: usize,
+    pub pc: MatrixPoint,
+    pub direction: Direction,
+    pub stack: Vec<i64>,
+    pub register_a: u16,
+    pub calls: Vec<(MatrixPoint, Direction)>,
+}
+
+impl Thread {
+    pub fn new(id: usize, pc: MatrixPoint) -> Self {
+        Thread {
+            id,
+            pc,
+            direction: Direction::East,
+            stack: vec![],
+            register_a: 0,
+            calls: vec![],
+        }
+    }
+}
+
+/// How a step changed which thread is running, so that it can be undone
+#[derive(Debug)]
+pub(super) enum Switch {
+    /// The thread that ran went to the back of the queue
+    Rotated,
+    /// The thread that ran finished, in this state
+    Ended(Thread),
+}
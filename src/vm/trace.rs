@@ -0,0 +1,47 @@
+use super::Direction;
+use crate::{Condition, Instruction, MatrixPoint};
+
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// One executed instruction, as written to the trace
+#[derive(Serialize)]
+pub(super) struct Record<'a> {
+    pub step: u64,
+    /// The thread that ran the instruction, only recorded with concurrency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<usize>,
+    /// Where the instruction is, rather than its argument
+    pub point: MatrixPoint,
+    pub direction: Direction,
+    pub instruction: Instruction,
+    /// Hue of the argument pixel, for instructions that take one
+    pub arg: Option<u16>,
+    /// Only recorded for conditional instructions
+    pub condition: Option<Condition>,
+    /// The stack after the instruction ran
+    pub stack: &'a [i64],
+    pub register_a: u16,
+    /// `(address, value)` for each tape cell the instruction wrote to
+    pub tape_writes: &'a [(usize, i64)],
+}
+
+/// Writes a JSON Lines trace with one record per executed instruction
+pub(super) struct Tracer {
+    out: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Tracer { out }
+    }
+
+    pub fn record(&mut self, record: &Record) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, record)?;
+        writeln!(self.out)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -1,9 +1,10 @@
+use std::io;
 use std::str;
 
 #[test]
 fn test() {
     let mut buffer: Vec<u8> = vec![];
-    quilt_lang::run("examples/hello_world.png", 1, &mut buffer);
+    quilt_lang::run("examples/hello_world.png", 1, io::empty(), &mut buffer).unwrap();
     let s = str::from_utf8(&buffer).unwrap();
     assert_eq!(s, "Hello world!");
 }
@@ -11,7 +12,13 @@ fn test() {
 #[test]
 fn test1() {
     let mut buffer: Vec<u8> = vec![];
-    quilt_lang::run("examples/hello_world_elaborate.png", 1, &mut buffer);
+    quilt_lang::run(
+        "examples/hello_world_elaborate.png",
+        1,
+        io::empty(),
+        &mut buffer,
+    )
+    .unwrap();
     let s = str::from_utf8(&buffer).unwrap();
     assert_eq!(s, "Hello world!\n");
 }
@@ -19,7 +26,7 @@ fn test1() {
 #[test]
 fn test_fib() {
     let mut buffer: Vec<u8> = vec![];
-    quilt_lang::run("examples/fib_6.png", 1, &mut buffer);
+    quilt_lang::run("examples/fib_6.png", 1, io::empty(), &mut buffer).unwrap();
     let s = str::from_utf8(&buffer).unwrap();
     assert_eq!(s, "1 1 2 3 5 8 ");
 }